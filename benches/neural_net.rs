@@ -0,0 +1,98 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use evolution::*;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+/// A lifeform with a mutated genome of the given shape and random input values.
+fn lifeform(nnh: &NeuralNetHelper, genome_size: usize, num_inner_neurons: usize) -> LifeForm {
+    let mut rng = ChaCha8Rng::seed_from_u64(1);
+    let mut innovations = Innovations::new();
+
+    let props = MutationProps {
+        max_genome_size: genome_size * 2,
+        ..MutationProps::default()
+    };
+
+    let mut lf = LifeForm::new(
+        0,
+        (0, 0),
+        GenomeProps {
+            size: genome_size,
+            num_inner_neurons,
+            mutation_rate: 0.1,
+            neural_net_helper: nnh,
+        },
+        &mut innovations,
+        &mut rng,
+    );
+
+    for _ in 0..10 {
+        Evolver::mutate(&mut lf.genome, &props, nnh, &mut innovations, &mut rng);
+    }
+    lf.neural_net = nnh.spawn(&lf.genome);
+
+    for value in lf.neural_net.program.inputs.iter_mut() {
+        *value = rng.gen_range(-1.0..=1.0);
+    }
+
+    lf
+}
+
+/// The compiled program against the original HashMap walk, which it has to match exactly.
+fn run_neural_net(c: &mut Criterion) {
+    let nnh = NeuralNetHelper::new();
+    let mut group = c.benchmark_group("run_neural_net");
+
+    for (genome_size, num_inner_neurons) in [(25, 5), (100, 5), (100, 20)] {
+        let mut lf = lifeform(&nnh, genome_size, num_inner_neurons);
+        assert_eq!(
+            lf.clone().run_neural_net(false),
+            lf.clone().run_neural_net_reference(&nnh, false)
+        );
+
+        let name = format!("{}_genes_{}_inner", genome_size, num_inner_neurons);
+
+        group.bench_function(BenchmarkId::new("reference", &name), |b| {
+            b.iter(|| black_box(lf.run_neural_net_reference(&nnh, false)))
+        });
+
+        group.bench_function(BenchmarkId::new("compiled", &name), |b| {
+            b.iter(|| black_box(lf.run_neural_net(false)))
+        });
+    }
+
+    group.finish();
+}
+
+/// Working out the order genes are walked in, which happens for every new genome.
+fn ordered_gene_indices(c: &mut Criterion) {
+    let nnh = NeuralNetHelper::new();
+    let mut group = c.benchmark_group("compute_ordered_gene_indices");
+
+    for (genome_size, num_inner_neurons) in [(25, 5), (100, 5), (100, 20), (250, 50)] {
+        let lf = lifeform(&nnh, genome_size, num_inner_neurons);
+        let name = format!("{}_genes_{}_inner", genome_size, num_inner_neurons);
+
+        group.bench_function(BenchmarkId::from_parameter(name), |b| {
+            b.iter(|| {
+                black_box(compute_ordered_gene_indices(
+                    &lf.genome.genes,
+                    lf.genome.num_inner_neurons,
+                    &nnh,
+                ))
+            })
+        });
+    }
+
+    group.finish();
+}
+
+fn spawn(c: &mut Criterion) {
+    let nnh = NeuralNetHelper::new();
+    let lf = lifeform(&nnh, 25, 5);
+
+    c.bench_function("spawn", |b| b.iter(|| black_box(nnh.spawn(&lf.genome))));
+}
+
+criterion_group!(benches, run_neural_net, ordered_gene_indices, spawn);
+criterion_main!(benches);
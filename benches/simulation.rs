@@ -0,0 +1,110 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use evolution::*;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+const SIZE: usize = 100;
+const POPULATIONS: [usize; 3] = [50, 200, 1000];
+
+/// (genome size, inner neurons)
+const GENOME_SHAPES: [(usize, usize); 2] = [(25, 5), (100, 20)];
+
+/// A world with this many lifeforms spread out over the board and some food around, stepped a
+/// few times so the lifeforms have moved and sensed something.
+fn world(
+    nnh: &NeuralNetHelper,
+    num_lifeforms: usize,
+    genome_size: usize,
+    num_inner_neurons: usize,
+) -> World<'_> {
+    let mut world = World::new(
+        WorldProps {
+            size: SIZE,
+            num_initial_lifeforms: num_lifeforms,
+            minimum_number_lifeforms: num_lifeforms,
+            genome_size,
+            num_inner_neurons,
+            mutation: MutationProps {
+                max_genome_size: genome_size * 2,
+                max_inner_neurons: num_inner_neurons * 2,
+                ..MutationProps::default()
+            },
+            seed: 1,
+            ..WorldProps::default()
+        },
+        nnh,
+    );
+
+    let mut rng = ChaCha8Rng::seed_from_u64(1);
+
+    for _ in 0..SIZE {
+        let loc = (rng.gen_range(0..SIZE), rng.gen_range(0..SIZE));
+
+        if !world.walls.contains(&loc) {
+            world.food.insert(loc, Food::grown());
+        }
+    }
+
+    world.reindex();
+
+    for _ in 0..10 {
+        world.step();
+    }
+
+    world
+}
+
+fn name(num_lifeforms: usize, genome_size: usize, num_inner_neurons: usize) -> String {
+    format!(
+        "{}_lifeforms_{}_genes_{}_inner",
+        num_lifeforms, genome_size, num_inner_neurons
+    )
+}
+
+fn update_inputs(c: &mut Criterion) {
+    let nnh = NeuralNetHelper::new();
+    let mut group = c.benchmark_group("update_inputs");
+
+    for num_lifeforms in POPULATIONS {
+        for (genome_size, num_inner_neurons) in GENOME_SHAPES {
+            let mut world = world(&nnh, num_lifeforms, genome_size, num_inner_neurons);
+            let id =
+                BenchmarkId::from_parameter(name(num_lifeforms, genome_size, num_inner_neurons));
+
+            group.bench_function(id, |b| b.iter(|| world.update_inputs()));
+        }
+    }
+
+    group.finish();
+}
+
+/// A whole tic. Every iteration steps the same world from the same point, so they're comparable.
+fn step(c: &mut Criterion) {
+    let nnh = NeuralNetHelper::new();
+    let mut group = c.benchmark_group("step");
+    group.sample_size(20);
+
+    for num_lifeforms in POPULATIONS {
+        for (genome_size, num_inner_neurons) in GENOME_SHAPES {
+            let snapshot = world(&nnh, num_lifeforms, genome_size, num_inner_neurons).snapshot();
+            let id =
+                BenchmarkId::from_parameter(name(num_lifeforms, genome_size, num_inner_neurons));
+
+            group.bench_function(id, |b| {
+                b.iter_batched(
+                    || World::from_snapshot(snapshot.clone(), &nnh),
+                    |mut world| {
+                        world.step();
+                        black_box(world)
+                    },
+                    BatchSize::LargeInput,
+                )
+            });
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, update_inputs, step);
+criterion_main!(benches);
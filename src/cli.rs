@@ -1,55 +1,278 @@
+use std::path::PathBuf;
+
 use clap::Parser;
 
+use crate::*;
+
 /// An evolutionary ecosystem with LifeForms, food, and danger. Customize some input variables, or
 /// don't. Each run will yield different behaviors as the lifeforms evolve and adapt to their
 /// environment.
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 pub struct Args {
+    /// Read the world's settings from a TOML or JSON file (picked by the file's extension). Any
+    /// of the options below given on the command line override what's in the file. See
+    /// evolution.example.toml for every setting a file can hold, and the default each of the
+    /// options below has when neither gives it.
+    #[arg(long)]
+    pub config: Option<PathBuf>,
 
     /// How big is the world? The world is square (note that terminals will show a much bigger
     /// height since each space is a character which is taller than it is wide).
-    #[arg(long, default_value_t = 50)]
-    pub size: usize,
+    #[arg(long)]
+    pub size: Option<usize>,
 
     /// How many lifeforms should start on the board?
-    #[arg(long, default_value_t = 20)]
-    pub num_initial_lifeforms: usize,
+    #[arg(long)]
+    pub num_initial_lifeforms: Option<usize>,
 
     /// How many genes are there? Each gene represents one connection from neuron to neuron.
-    #[arg(long, default_value_t = 25)]
-    pub genome_size: usize,
+    #[arg(long)]
+    pub genome_size: Option<usize>,
 
     /// What are the chances of a mutation occuring when a lifeform splits when it eats enough
-    /// food?
-    #[arg(long, default_value_t = 0.1)]
-    pub mutation_rate: f32,
+    /// food? This is where the lifeforms start, each genome carries its own rate that can
+    /// evolve (see --rate-sd).
+    #[arg(long)]
+    pub mutation_rate: Option<f32>,
 
     /// After how many frames does a new food appear?
-    #[arg(long, default_value_t = 30)]
-    pub food_density: usize,
+    #[arg(long)]
+    pub food_density: Option<usize>,
 
     /// How many inner neurons should there be? These are neurons that don't have input values
     /// based on the world around them, and don't result in a physical action by the lifeform.
-    #[arg(long, default_value_t = 5)]
-    pub num_inner_neurons: usize,
+    /// This is only where the lifeforms start, mutation can grow or shrink it.
+    #[arg(long)]
+    pub num_inner_neurons: Option<usize>,
+
+    /// The most genes mutation is allowed to grow a genome to.
+    #[arg(long)]
+    pub max_genome_size: Option<usize>,
+
+    /// The most inner neurons mutation is allowed to grow a genome to. Can't go above 100.
+    #[arg(long)]
+    pub max_inner_neurons: Option<usize>,
 
     /// What is the minimum number of lifeforms on the board at any time before new ones are
     /// created? The new creation scheme looks at the most fit individual and makes some clones of
-    /// that one, and also makes a random new lifeform.
-    #[arg(long, default_value_t = 5)]
-    pub minimum_number_lifeforms: usize,
+    /// that one. If every lifeform has died, a batch of random new ones is made instead.
+    #[arg(long)]
+    pub minimum_number_lifeforms: Option<usize>,
+
+    /// How far a weight is nudged by a weight perturbing mutation, as the standard deviation of
+    /// a normal distribution.
+    #[arg(long)]
+    pub perturb_sd: Option<f32>,
+
+    /// How far each genome's own mutation rate drifts when it's passed on, which lets the
+    /// population evolve its mutation rate. 0 keeps every genome at --mutation-rate.
+    #[arg(long)]
+    pub rate_sd: Option<f32>,
+
+    /// How a child's genome is put together from its parents' when two lifeforms mate.
+    #[arg(long, value_enum)]
+    pub crossover: Option<Crossover>,
+
+    /// How many cuts k-point crossover makes.
+    #[arg(long)]
+    pub crossover_points: Option<usize>,
+
+    /// How far apart two genomes can be and still be the same species. See Genome::distance for
+    /// how that's measured.
+    #[arg(long)]
+    pub species_threshold: Option<f32>,
+
+    /// After how many tics the lifeforms are sorted into species again.
+    #[arg(long)]
+    pub species_interval: Option<usize>,
+
+    /// Let inner neurons keep their values from one tic to the next (true or false), so the
+    /// lifeforms can evolve some short term memory.
+    #[arg(long)]
+    pub recurrent: Option<bool>,
+
+    /// Read the world's walls from a text file where every `#` is a wall, drawn the way the world
+    /// is shown (the first line is the top). Walls block lifeforms and the danger.
+    #[arg(long)]
+    pub map: Option<PathBuf>,
+
+    /// Without a map, about what fraction of the world is covered in randomly placed walls.
+    #[arg(long)]
+    pub wall_density: Option<f32>,
+
+    /// Whether the world stops at its edges, or wraps around them so that walking off one side
+    /// comes back in on the other.
+    #[arg(long, value_enum)]
+    pub topology: Option<Topology>,
+
+    /// How many clones of the most fit lifeform are made when there are too few lifeforms.
+    #[arg(long)]
+    pub num_clones: Option<usize>,
 
     /// After how many frames is the danger allowed to move one space?
-    #[arg(long, default_value_t = 10)]
-    pub danger_delay: usize,
+    #[arg(long)]
+    pub danger_delay: Option<usize>,
 
     /// The danger is regarded as radioactive -- this means that it does more damage to a lifeform
     /// the closer the lifeform is to it. The effect of the danger falls off as the square of the
     /// distance from it.
-    #[arg(long, default_value_t = 0.5)]
-    pub danger_damage: f32,
+    #[arg(long)]
+    pub danger_damage: Option<f32>,
+
+    /// Put a danger that behaves this way in the world, starting somewhere random. Give it more
+    /// than once for more dangers. These replace the world's usual hunter, and any dangers from
+    /// the config file.
+    #[arg(long = "hazard", value_enum)]
+    pub hazards: Vec<HazardBehavior>,
+
+    /// How much hungrier every lifeform gets each tic.
+    #[arg(long)]
+    pub hunger_per_tic: Option<f32>,
+
+    /// How much health a lifeform gets back from eating a food.
+    #[arg(long)]
+    pub food_health: Option<f32>,
+
+    /// How much less hungry a lifeform is after eating a food.
+    #[arg(long)]
+    pub food_hunger: Option<f32>,
+
+    /// What fraction of their health both the attacker and the attacked keep after an attack.
+    #[arg(long)]
+    pub attack_health_factor: Option<f32>,
+
+    /// How much hungrier attacking makes the attacker.
+    #[arg(long)]
+    pub attack_hunger: Option<f32>,
+
+    /// How many of the most recent events are kept for the UI.
+    #[arg(long)]
+    pub events_length: Option<usize>,
+
+    /// Seed for the world's randomness. Two runs with the same seed and the same options play out
+    /// exactly the same way. A random seed is picked when this isn't given; it's shown in the UI
+    /// so an interesting run can be repeated.
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Run without the terminal UI, stepping the world as fast as possible and printing stats to
+    /// stdout. Needs --tics to know when to stop.
+    #[arg(long, requires = "tics")]
+    pub headless: bool,
+
+    /// How many tics to run for in headless mode.
+    #[arg(long)]
+    pub tics: Option<usize>,
+
+    /// In headless mode, print a line of stats after every this many tics. 0 prints only the
+    /// final summary.
+    #[arg(long, default_value_t = 1000)]
+    pub report_interval: usize,
+
+    /// Record a row of population statistics (population, ages, health, hunger, births, deaths,
+    /// attacks, food and genome diversity) after every this many tics.
+    #[arg(long)]
+    pub stats_interval: Option<usize>,
+
+    /// When the app exits, write the recorded population statistics to this file.
+    #[arg(long)]
+    pub stats_file: Option<PathBuf>,
+
+    /// The format the statistics are written in.
+    #[arg(long, value_enum, default_value_t = StatsFormat::Csv)]
+    pub stats_format: StatsFormat,
+
+    /// When the app exits, write the family tree of every lifeform that lived to this file in
+    /// Newick format. Branch lengths are in tics.
+    #[arg(long)]
+    pub newick_file: Option<PathBuf>,
+
+    /// When the app exits, write the family tree of every lifeform that lived to this file as
+    /// nested JSON.
+    #[arg(long)]
+    pub tree_file: Option<PathBuf>,
+
+    /// Resume a world previously saved from within the app. The world's settings come from the
+    /// save file, so --config and the world options above are ignored when this is given.
+    #[arg(long)]
+    pub load: Option<PathBuf>,
+
+    /// Where the world is written to when saving it from within the app.
+    #[arg(long, default_value = "evolution.json")]
+    pub save_file: PathBuf,
+}
+
+impl Args {
+    /// Builds the world's props: defaults, then the config file if there is one, then whatever
+    /// was given on the command line. The result is validated.
+    pub fn world_props(&self) -> Result<WorldProps, ConfigError> {
+        let mut props = match &self.config {
+            Some(path) => WorldProps::load(path)?,
+            None => WorldProps::default(),
+        };
+
+        macro_rules! override_props {
+            ($($arg:ident => $($field:ident).+),* $(,)?) => {
+                $(
+                    if let Some(value) = self.$arg {
+                        props.$($field).+ = value;
+                    }
+                )*
+            };
+        }
+
+        override_props!(
+            size => size,
+            num_initial_lifeforms => num_initial_lifeforms,
+            genome_size => genome_size,
+            mutation_rate => mutation_rate,
+            food_density => food_density,
+            num_inner_neurons => num_inner_neurons,
+            max_genome_size => mutation.max_genome_size,
+            max_inner_neurons => mutation.max_inner_neurons,
+            perturb_sd => mutation.perturb_sd,
+            rate_sd => mutation.rate_sd,
+            crossover => crossover.strategy,
+            crossover_points => crossover.points,
+            species_threshold => species.threshold,
+            species_interval => species.interval,
+            wall_density => walls.density,
+            topology => topology,
+            minimum_number_lifeforms => minimum_number_lifeforms,
+            num_clones => num_clones,
+            danger_delay => danger_delay,
+            danger_damage => danger_damage,
+            hunger_per_tic => hunger_per_tic,
+            food_health => food_health,
+            food_hunger => food_hunger,
+            attack_health_factor => attack_health_factor,
+            attack_hunger => attack_hunger,
+            events_length => events_length,
+            seed => seed,
+            stats_interval => stats_interval,
+            recurrent => recurrent,
+        );
+
+        if !self.hazards.is_empty() {
+            props.hazards = self
+                .hazards
+                .iter()
+                .map(|behavior| HazardProps {
+                    behavior: *behavior,
+                    ..HazardProps::default()
+                })
+                .collect();
+        }
+
+        if let Some(map) = &self.map {
+            props.walls.map = Some(map.clone());
+        }
+
+        props.validate()?;
 
+        Ok(props)
+    }
 }
 
 //         size,
@@ -0,0 +1,358 @@
+use std::{fmt, fs, io, path::Path};
+
+use crate::*;
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Parse(String),
+
+    /// A setting that was read fine but doesn't make sense, like a mutation rate of 2
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "could not read config: {}", e),
+            ConfigError::Parse(e) => write!(f, "could not parse config: {}", e),
+            ConfigError::Invalid(e) => write!(f, "invalid setting: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<io::Error> for ConfigError {
+    fn from(e: io::Error) -> Self {
+        ConfigError::Io(e)
+    }
+}
+
+impl WorldProps {
+    /// Reads props from a TOML or JSON file, going by its extension. Anything the file leaves out
+    /// keeps its default value. This doesn't validate, since command line flags may still
+    /// override what's in the file.
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let contents = fs::read_to_string(path)?;
+
+        let is_json = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+
+        if is_json {
+            serde_json::from_str(&contents).map_err(|e| ConfigError::Parse(e.to_string()))
+        } else {
+            toml::from_str(&contents).map_err(|e| ConfigError::Parse(e.to_string()))
+        }
+    }
+
+    /// Makes sure every setting is in a range the world can actually run with.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |msg: String| Err(ConfigError::Invalid(msg));
+
+        if self.size == 0 {
+            return invalid(String::from("size must be at least 1"));
+        }
+
+        if self.genome_size == 0 {
+            return invalid(String::from("genome_size must be at least 1"));
+        }
+
+        if self.genome_size > self.mutation.max_genome_size {
+            return invalid(format!(
+                "genome_size ({}) can't be more than max_genome_size ({})",
+                self.genome_size, self.mutation.max_genome_size
+            ));
+        }
+
+        if self.mutation.max_inner_neurons > MAX_INNER_NEURONS {
+            return invalid(format!(
+                "max_inner_neurons can't be more than {}",
+                MAX_INNER_NEURONS
+            ));
+        }
+
+        if self.num_inner_neurons > self.mutation.max_inner_neurons {
+            return invalid(format!(
+                "num_inner_neurons ({}) can't be more than max_inner_neurons ({})",
+                self.num_inner_neurons, self.mutation.max_inner_neurons
+            ));
+        }
+
+        let rates = &self.mutation.rates;
+
+        for (name, value) in [
+            ("perturb_sd", self.mutation.perturb_sd),
+            ("rate_sd", self.mutation.rate_sd),
+            ("perturb_weight", rates.perturb_weight),
+            ("replace_weight", rates.replace_weight),
+            ("rewire", rates.rewire),
+            ("duplicate_gene", rates.duplicate_gene),
+            ("toggle_gene", rates.toggle_gene),
+            ("add_gene", rates.add_gene),
+            ("remove_gene", rates.remove_gene),
+            ("add_inner_neuron", rates.add_inner_neuron),
+            ("remove_inner_neuron", rates.remove_inner_neuron),
+            ("perturb_bias", rates.perturb_bias),
+            ("change_activation", rates.change_activation),
+        ] {
+            if !(value >= 0.0 && value.is_finite()) {
+                return invalid(format!("{} can't be negative, got {}", name, value));
+            }
+        }
+
+        if self.crossover.strategy == Crossover::KPoint && self.crossover.points == 0 {
+            return invalid(String::from("k-point crossover needs at least 1 point"));
+        }
+
+        for (name, value) in [
+            ("danger_delay", self.danger_delay),
+            ("food_density", self.food_density),
+            ("stats_interval", self.stats_interval),
+            ("events_length", self.events_length),
+            ("species.interval", self.species.interval),
+            ("walls.max_length", self.walls.max_length),
+            ("food.spread_radius", self.food.spread_radius),
+            ("food.carrying_capacity", self.food.carrying_capacity),
+        ] {
+            if value == 0 {
+                return invalid(format!("{} must be at least 1", name));
+            }
+        }
+
+        for (name, value) in [
+            ("mutation_rate", self.mutation_rate),
+            ("attack_health_factor", self.attack_health_factor),
+            ("food.growth", self.food.growth),
+            ("food.spread_chance", self.food.spread_chance),
+        ] {
+            if !(0.0..=1.0).contains(&value) {
+                return invalid(format!("{} must be between 0 and 1, got {}", name, value));
+            }
+        }
+
+        for (name, value) in [
+            ("danger_damage", self.danger_damage),
+            ("hunger_per_tic", self.hunger_per_tic),
+            ("food_health", self.food_health),
+            ("food_hunger", self.food_hunger),
+            ("carrion.nutrition", self.carrion.nutrition),
+            ("carrion.decay", self.carrion.decay),
+            ("attack_hunger", self.attack_hunger),
+            ("species.threshold", self.species.threshold),
+            (
+                "species.disjoint_coefficient",
+                self.species.disjoint_coefficient,
+            ),
+            (
+                "species.weight_coefficient",
+                self.species.weight_coefficient,
+            ),
+        ] {
+            if !(value >= 0.0 && value.is_finite()) {
+                return invalid(format!("{} can't be negative, got {}", name, value));
+            }
+        }
+
+        // Children start out with their parents' mate_cost as their health
+        if !(self.mate_cost > 0.0 && self.mate_cost.is_finite()) {
+            return invalid(format!("mate_cost must be above 0, got {}", self.mate_cost));
+        }
+
+        for (i, hazard) in self.hazards.iter().enumerate() {
+            if hazard.delay == Some(0) {
+                return invalid(format!("hazard {} must have a delay of at least 1", i));
+            }
+
+            if !hazard
+                .damage
+                .is_none_or(|damage| damage >= 0.0 && damage.is_finite())
+            {
+                return invalid(format!("hazard {} can't have negative damage", i));
+            }
+
+            if !(hazard.radius > 0.0 && hazard.radius.is_finite()) {
+                return invalid(format!("hazard {} needs a radius above 0", i));
+            }
+
+            let size = self.size;
+            let off_the_world = hazard
+                .start
+                .iter()
+                .chain(&hazard.path)
+                .find(|(x, y)| *x >= size || *y >= size);
+
+            if let Some(loc) = off_the_world {
+                return invalid(format!(
+                    "hazard {} goes to {:?}, which is off the world",
+                    i, loc
+                ));
+            }
+        }
+
+        // Walls everywhere would leave nowhere to put anything
+        if !(0.0..1.0).contains(&self.walls.density) {
+            return invalid(format!(
+                "walls.density must be at least 0 and less than 1, got {}",
+                self.walls.density
+            ));
+        }
+
+        if let Some(map) = &self.walls.map {
+            let walls = load_walls(map, self.size)?;
+
+            // New food and lifeforms go somewhere below size on both axes
+            let size = self.size;
+            let covered = walls.iter().filter(|(x, y)| *x < size && *y < size).count();
+
+            if covered >= size * size {
+                return invalid(format!("the map {} has no open spaces", map.display()));
+            }
+
+            for (i, hazard) in self.hazards.iter().enumerate() {
+                let walled_in = hazard
+                    .start
+                    .iter()
+                    .chain(&hazard.path)
+                    .find(|loc| walls.contains(loc));
+
+                if let Some(loc) = walled_in {
+                    return invalid(format!(
+                        "hazard {} goes to {:?}, which is a wall on the map",
+                        i, loc
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn write_temp(name: &str, contents: &str) -> std::path::PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("evolution-config-{}-{}", std::process::id(), name));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn reads_partial_toml_and_json() {
+        let toml_path = write_temp(
+            "props.toml",
+            "size = 80\nnum_clones = 7\n\n[mutation]\nmax_inner_neurons = 9\n",
+        );
+        let json_path = write_temp("props.json", r#"{ "size": 60, "food_health": 0.1 }"#);
+
+        let from_toml = WorldProps::load(&toml_path).unwrap();
+        let from_json = WorldProps::load(&json_path).unwrap();
+        fs::remove_file(&toml_path).unwrap();
+        fs::remove_file(&json_path).unwrap();
+
+        assert_eq!(from_toml.size, 80);
+        assert_eq!(from_toml.num_clones, 7);
+        assert_eq!(from_toml.mutation.max_inner_neurons, 9);
+        assert_eq!(from_toml.mutation.max_genome_size, 100);
+        assert_eq!(from_toml.events_length, 30);
+
+        assert_eq!(from_json.size, 60);
+        assert_eq!(from_json.food_health, 0.1);
+        assert_eq!(from_json.genome_size, 25);
+    }
+
+    #[test]
+    fn rejects_misspelled_settings() {
+        for (name, contents) in [
+            ("typo.toml", "sise = 80\n"),
+            ("nested-typo.toml", "[mutation.rates]\nadd_gen = 0.5\n"),
+            (
+                "hazard-typo.json",
+                r#"{ "hazards": [{ "behaviour": "hunter" }] }"#,
+            ),
+        ] {
+            let path = write_temp(name, contents);
+            let loaded = WorldProps::load(&path);
+            fs::remove_file(&path).unwrap();
+            assert!(matches!(loaded, Err(ConfigError::Parse(_))), "{}", name);
+        }
+    }
+
+    #[test]
+    fn the_example_config_is_all_defaults() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("evolution.example.toml");
+        let mut example = WorldProps::load(&path).unwrap();
+        let defaults = WorldProps::default();
+
+        // The seed is random unless it's given
+        example.seed = defaults.seed;
+
+        assert_eq!(
+            serde_json::to_value(&example).unwrap(),
+            serde_json::to_value(&defaults).unwrap()
+        );
+    }
+
+    #[test]
+    fn rejects_out_of_range_settings() {
+        assert!(WorldProps::default().validate().is_ok());
+
+        let props = WorldProps {
+            mutation_rate: 1.5,
+            ..WorldProps::default()
+        };
+        assert!(matches!(props.validate(), Err(ConfigError::Invalid(_))));
+
+        let props = WorldProps {
+            num_inner_neurons: 21,
+            ..WorldProps::default()
+        };
+        assert!(props.validate().is_err());
+
+        let props = WorldProps {
+            food_density: 0,
+            ..WorldProps::default()
+        };
+        assert!(props.validate().is_err());
+
+        let props = WorldProps {
+            mate_cost: 0.0,
+            ..WorldProps::default()
+        };
+        assert!(props.validate().is_err());
+
+        // Spaces go from 0 to size - 1
+        let hazard_at = |start| WorldProps {
+            size: 10,
+            hazards: vec![HazardProps {
+                start: Some(start),
+                ..HazardProps::default()
+            }],
+            ..WorldProps::default()
+        };
+        assert!(hazard_at((9, 9)).validate().is_ok());
+        assert!(hazard_at((10, 3)).validate().is_err());
+
+        // Nor on the map's walls. The map's first line is y = size, so its second is 9.
+        let map = write_temp("hazard-map.txt", "\n#\n");
+        let props = WorldProps {
+            walls: WallProps {
+                map: Some(map.clone()),
+                ..WallProps::default()
+            },
+            ..hazard_at((0, 9))
+        };
+        let walled_in = props.validate();
+        fs::remove_file(&map).unwrap();
+        assert!(walled_in.is_err());
+
+        let path = write_temp("bad.toml", "size = \"big\"");
+        let loaded = WorldProps::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(loaded, Err(ConfigError::Parse(_))));
+    }
+}
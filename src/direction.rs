@@ -1,3 +1,4 @@
+use serde::{Deserialize, Serialize};
 use strum_macros::EnumIter;
 
 // Direction needs to do two things:
@@ -6,7 +7,7 @@ use strum_macros::EnumIter;
 // case.
 // I do not like this implementation. I think it should be able to happen with an enum.
 // Direction should be an enum that simply knows how to increment and decrement itself.
-static DIRECTIONS: &'static [(i8, i8)] = &[
+static DIRECTIONS: &[(i8, i8)] = &[
     (0, 1), // 0 = North
     (1, 1), // 1 = NorthEast
     (1, 0), // 2 = East
@@ -17,11 +18,17 @@ static DIRECTIONS: &'static [(i8, i8)] = &[
     (-1, 1), // 7 = NorthWest
 ];
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Direction {
     direction: u8,
 }
 
+impl Default for Direction {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Direction {
     pub fn new() -> Self {
         Self { direction: 0 }
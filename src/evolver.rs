@@ -1,26 +1,201 @@
-use rand::{thread_rng, Rng};
+use std::collections::BTreeMap;
+
+use clap::ValueEnum;
+use rand::{prelude::Distribution, seq::SliceRandom, Rng};
+use rand_distr::{Normal, Poisson};
+use serde::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
 
 use crate::*;
 
+/// How genomes mutate, and the limits on how far mutation can grow them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MutationProps {
+    /// The most genes a genome can grow to
+    pub max_genome_size: usize,
+
+    /// The most inner neurons a genome can grow to. Can't be more than MAX_INNER_NEURONS.
+    pub max_inner_neurons: usize,
+
+    /// The standard deviation of the nudge a weight gets from a PerturbWeight mutation, and a
+    /// bias from a PerturbBias one
+    pub perturb_sd: f32,
+
+    /// How far a genome's mutation rate drifts each time it's passed on. The rate is multiplied
+    /// by e to the power of a normally distributed number with this standard deviation, so it
+    /// moves by about the same proportion whether it's big or small. 0 keeps every genome at
+    /// the starting rate.
+    pub rate_sd: f32,
+
+    /// How many of each kind of mutation a genome gets
+    pub rates: MutationRates,
+}
+
+impl Default for MutationProps {
+    fn default() -> Self {
+        Self {
+            max_genome_size: 100,
+            max_inner_neurons: 20,
+            perturb_sd: 0.5,
+            rate_sd: 0.2,
+            rates: MutationRates::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, EnumIter)]
+pub enum MutationKind {
+    /// Nudge a gene's weight by a normally distributed amount
+    PerturbWeight,
+    /// Give a gene a whole new random weight
+    ReplaceWeight,
+    /// Point one end of a gene at a different neuron
+    Rewire,
+    /// Copy a gene, so the copy can drift away from the original
+    DuplicateGene,
+    /// Switch a gene off, or back on
+    ToggleGene,
+    AddGene,
+    RemoveGene,
+    AddInnerNeuron,
+    RemoveInnerNeuron,
+    /// Nudge an inner or output neuron's bias by a normally distributed amount
+    PerturbBias,
+    /// Give an inner or output neuron a different activation function
+    ChangeActivation,
+}
+
+/// How many of each kind of mutation a genome gets, on average, every time it mutates. The
+/// actual number of each kind is drawn from a Poisson distribution with that mean, independently
+/// of every other kind, so one kind can be made more or less common without touching the rest,
+/// and the number of mutations varies from one birth to the next. 0 switches a kind off.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MutationRates {
+    pub perturb_weight: f32,
+    pub replace_weight: f32,
+    pub rewire: f32,
+    pub duplicate_gene: f32,
+    pub toggle_gene: f32,
+    pub add_gene: f32,
+    pub remove_gene: f32,
+    pub add_inner_neuron: f32,
+    pub remove_inner_neuron: f32,
+    pub perturb_bias: f32,
+    pub change_activation: f32,
+}
+
+// One mutation per birth on average
+impl Default for MutationRates {
+    fn default() -> Self {
+        Self {
+            perturb_weight: 0.32,
+            replace_weight: 0.08,
+            rewire: 0.16,
+            duplicate_gene: 0.04,
+            toggle_gene: 0.04,
+            add_gene: 0.08,
+            remove_gene: 0.08,
+            add_inner_neuron: 0.04,
+            remove_inner_neuron: 0.04,
+            perturb_bias: 0.08,
+            change_activation: 0.04,
+        }
+    }
+}
+
+impl MutationRates {
+    pub fn get(&self, kind: MutationKind) -> f32 {
+        match kind {
+            MutationKind::PerturbWeight => self.perturb_weight,
+            MutationKind::ReplaceWeight => self.replace_weight,
+            MutationKind::Rewire => self.rewire,
+            MutationKind::DuplicateGene => self.duplicate_gene,
+            MutationKind::ToggleGene => self.toggle_gene,
+            MutationKind::AddGene => self.add_gene,
+            MutationKind::RemoveGene => self.remove_gene,
+            MutationKind::AddInnerNeuron => self.add_inner_neuron,
+            MutationKind::RemoveInnerNeuron => self.remove_inner_neuron,
+            MutationKind::PerturbBias => self.perturb_bias,
+            MutationKind::ChangeActivation => self.change_activation,
+        }
+    }
+}
+
+/// The ways two genomes can be combined into a child's.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Crossover {
+    /// Cut both genomes at the same random spot, take the front of one and the back of the other
+    SinglePoint,
+
+    /// Like single point, but with CrossoverProps::points cuts, switching parents at each one
+    KPoint,
+
+    /// Every gene is picked from either parent by a coin toss
+    Uniform,
+
+    /// Line genes up by their id rather than their position, the way NEAT uses innovation
+    /// numbers. Genes both parents have are picked by a coin toss, the rest are each kept half
+    /// the time. A duplicated gene lines its copies up in order with the other parent's copies.
+    /// If that leaves more genes than the bigger parent has, random ones are dropped.
+    GeneId,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CrossoverProps {
+    pub strategy: Crossover,
+
+    /// How many cuts k-point crossover makes
+    pub points: usize,
+}
+
+impl Default for CrossoverProps {
+    fn default() -> Self {
+        Self {
+            strategy: Crossover::SinglePoint,
+            points: 2,
+        }
+    }
+}
+
+// A gene from each parent, if they have it
+type GenePair<'a> = (Option<&'a Gene>, Option<&'a Gene>);
+
 #[derive(Debug)]
 pub struct Evolver {}
 
 impl Evolver {
-    pub fn mate(genome1: &Genome, genome2: &Genome, nnh: &NeuralNetHelper) -> Genome {
-        let mut genes = vec![];
-
-        // TODO parallelize
-        for i in 0..genome1.genes.len() / 2 {
-            genes.push(genome1.genes[i].clone());
-        }
+    /// The genomes can be of different lengths, and the child gets the larger of the two inner
+    /// neuron counts so every gene it inherits still points at a neuron it has. The child is
+    /// never longer than the longer parent and always has at least one gene.
+    pub fn mate(
+        genome1: &Genome,
+        genome2: &Genome,
+        props: &CrossoverProps,
+        nnh: &NeuralNetHelper,
+        rng: &mut impl Rng,
+    ) -> Genome {
+        let mut genes = match props.strategy {
+            Crossover::SinglePoint => Evolver::k_point_crossover(genome1, genome2, 1, rng),
+            Crossover::KPoint => Evolver::k_point_crossover(genome1, genome2, props.points, rng),
+            Crossover::Uniform => Evolver::uniform_crossover(genome1, genome2, rng),
+            Crossover::GeneId => Evolver::gene_id_crossover(genome1, genome2, rng),
+        };
 
-        for i in (genome2.genes.len() / 2)..(genome2.genes.len()) {
-            genes.push(genome2.genes[i].clone());
+        if genes.is_empty() {
+            genes.push(genome1.genes[0].clone());
         }
 
         let mut genome = Genome {
             genes,
             ordered_gene_indices: vec![], // will be computed after creation
+            num_inner_neurons: genome1.num_inner_neurons.max(genome2.num_inner_neurons),
+            mutation_rate: (genome1.mutation_rate + genome2.mutation_rate) / 2.0,
+            neurons: Evolver::neuron_crossover(genome1, genome2, rng),
         };
 
         genome.recompute_ordered_gene_indices(nnh);
@@ -28,33 +203,374 @@ impl Evolver {
         genome
     }
 
+    /// Neurons both parents have get their bias and activation from either one by a coin toss.
+    /// Neurons only one parent has (the extra inner neurons of the bigger brain) come from that
+    /// parent.
+    fn neuron_crossover(
+        genome1: &Genome,
+        genome2: &Genome,
+        rng: &mut impl Rng,
+    ) -> BTreeMap<usize, NeuronGene> {
+        let mut neurons = genome1.neurons.clone();
+
+        for (id, neuron) in &genome2.neurons {
+            match neurons.get_mut(id) {
+                Some(existing) => {
+                    if rng.gen_bool(0.5) {
+                        *existing = neuron.clone();
+                    }
+                }
+                None => {
+                    neurons.insert(*id, neuron.clone());
+                }
+            }
+        }
+
+        neurons
+    }
+
+    /// Cuts both genomes at the same k random positions and takes alternating stretches from
+    /// each, starting with genome1. Whichever parent is up after the last cut gives its whole
+    /// tail, so the child's length lands somewhere between the parents'.
+    fn k_point_crossover(
+        genome1: &Genome,
+        genome2: &Genome,
+        k: usize,
+        rng: &mut impl Rng,
+    ) -> Vec<Gene> {
+        let shortest = genome1.genes.len().min(genome2.genes.len());
+
+        // Cutting at either end wouldn't cross anything over
+        let mut cuts: Vec<usize> = if shortest < 2 {
+            vec![]
+        } else {
+            (0..k).map(|_| rng.gen_range(1..shortest)).collect()
+        };
+        cuts.sort_unstable();
+        cuts.dedup();
+
+        let parents = [&genome1.genes, &genome2.genes];
+        let mut genes = vec![];
+        let mut start = 0;
+
+        for (idx, cut) in cuts.iter().enumerate() {
+            genes.extend_from_slice(&parents[idx % 2][start..*cut]);
+            start = *cut;
+        }
+
+        genes.extend_from_slice(&parents[cuts.len() % 2][start..]);
+
+        genes
+    }
+
+    /// Goes position by position, picking a parent for each gene. Past the end of the shorter
+    /// parent, the longer parent's genes are each kept half the time.
+    fn uniform_crossover(genome1: &Genome, genome2: &Genome, rng: &mut impl Rng) -> Vec<Gene> {
+        let longest = genome1.genes.len().max(genome2.genes.len());
+        let mut genes = vec![];
+
+        for idx in 0..longest {
+            let gene = match (genome1.genes.get(idx), genome2.genes.get(idx)) {
+                (Some(g1), Some(g2)) => Some(if rng.gen_bool(0.5) { g1 } else { g2 }),
+                (Some(g), None) | (None, Some(g)) => rng.gen_bool(0.5).then_some(g),
+                (None, None) => None,
+            };
+
+            if let Some(gene) = gene {
+                genes.push(gene.clone());
+            }
+        }
+
+        genes
+    }
+
+    fn gene_id_crossover(genome1: &Genome, genome2: &Genome, rng: &mut impl Rng) -> Vec<Gene> {
+        // Genes are lined up by id and by which copy of that id they are, so a gene duplicated in
+        // one parent lines up its first copy with the other parent's first, and so on
+        let mut by_id: BTreeMap<(usize, usize), GenePair> = BTreeMap::new();
+
+        let mut copies = BTreeMap::new();
+        for gene in &genome1.genes {
+            let copy = copies.entry(gene.id).or_insert(0);
+            by_id.entry((gene.id, *copy)).or_default().0 = Some(gene);
+            *copy += 1;
+        }
+
+        let mut copies = BTreeMap::new();
+        for gene in &genome2.genes {
+            let copy = copies.entry(gene.id).or_insert(0);
+            by_id.entry((gene.id, *copy)).or_default().1 = Some(gene);
+            *copy += 1;
+        }
+
+        let mut genes = vec![];
+
+        for pair in by_id.values() {
+            let gene = match pair {
+                (Some(g1), Some(g2)) => Some(if rng.gen_bool(0.5) { g1 } else { g2 }),
+                (Some(g), None) | (None, Some(g)) => rng.gen_bool(0.5).then_some(g),
+                (None, None) => None,
+            };
+
+            if let Some(gene) = gene {
+                genes.push((*gene).clone());
+            }
+        }
+
+        // Parents that don't share much could otherwise have a child bigger than either of them,
+        // and past max_genome_size. The genes that go are picked at random, so newer genes are
+        // no more likely to be lost than older ones.
+        let max_len = genome1.genes.len().max(genome2.genes.len());
+
+        while genes.len() > max_len {
+            genes.remove(rng.gen_range(0..genes.len()));
+        }
+
+        genes
+    }
+
     pub fn fitness(lf: &LifeForm) -> usize {
         lf.lifespan
     }
 
-    pub fn should_mutate(mutation_rate: f32) -> bool {
-        thread_rng().gen_bool(mutation_rate as f64)
+    pub fn should_mutate(mutation_rate: f32, rng: &mut impl Rng) -> bool {
+        rng.gen_bool(mutation_rate as f64)
     }
 
-    /// Takes a mut ref to a genome and makes a slight mutation on the genome
-    pub fn mutate(genome: &mut Genome, nnh: &NeuralNetHelper) {
-        // First we just get one gene at random from the bunch
-        let idx = thread_rng().gen_range(0..genome.genes.len());
+    /// Lets a genome's own mutation rate drift a little, see MutationProps::rate_sd.
+    pub fn mutate_rate(genome: &mut Genome, props: &MutationProps, rng: &mut impl Rng) {
+        if props.rate_sd <= 0.0 {
+            return;
+        }
 
-        // Which of the three fields are we going to modify?
-        let from_to_weight = thread_rng().gen_range(0..3);
+        let factor: f32 = Normal::new(0.0, props.rate_sd).unwrap().sample(rng);
+        genome.mutation_rate = (genome.mutation_rate * factor.exp()).clamp(0.0, 1.0);
+    }
 
-        // Chooses one of weight, from, or to, from one of the genes, and changes it
-        if from_to_weight == 0 {
-            // TODO Maybe we should nudge this weight rather than replace it?
-            genome.genes[idx].weight = Genome::random_weight();
-        } else if from_to_weight == 1 {
-            genome.genes[idx].from = nnh.random_from_neuron(Some(genome.genes[idx].from));
-        } else {
-            genome.genes[idx].to = nnh.random_to_neuron(Some(genome.genes[idx].to));
+    /// Makes a random number of each kind of mutation to the genome, see MutationRates, in a
+    /// random order, and returns how many it made. A mutation that can't happen to the genome by
+    /// the time its turn comes (removing its only gene, say) is skipped.
+    pub fn mutate(
+        genome: &mut Genome,
+        props: &MutationProps,
+        nnh: &NeuralNetHelper,
+        innovations: &mut Innovations,
+        rng: &mut impl Rng,
+    ) -> usize {
+        let mut kinds = vec![];
+
+        for kind in MutationKind::iter() {
+            let rate = props.rates.get(kind);
+
+            if rate > 0.0 {
+                let count: f64 = Poisson::new(rate as f64).unwrap().sample(rng);
+                kinds.extend(std::iter::repeat_n(kind, count as usize));
+            }
+        }
+
+        kinds.shuffle(rng);
+
+        let mut count = 0;
+
+        for kind in kinds {
+            if Evolver::can_apply(genome, kind, props, nnh) {
+                Evolver::apply_mutation(genome, kind, props, nnh, innovations, rng);
+                count += 1;
+            }
         }
 
         genome.recompute_ordered_gene_indices(nnh);
+
+        count
+    }
+
+    fn can_apply(
+        genome: &Genome,
+        kind: MutationKind,
+        props: &MutationProps,
+        nnh: &NeuralNetHelper,
+    ) -> bool {
+        let max_inner_neurons = props.max_inner_neurons.min(MAX_INNER_NEURONS);
+        let has_room = genome.genes.len() < props.max_genome_size;
+
+        match kind {
+            MutationKind::Rewire => {
+                let (can_move_from, can_move_to) = Evolver::rewirable_ends(genome, nnh);
+                can_move_from || can_move_to
+            }
+            MutationKind::DuplicateGene | MutationKind::AddGene => has_room,
+            MutationKind::RemoveGene => genome.genes.len() > 1,
+            MutationKind::AddInnerNeuron => genome.num_inner_neurons < max_inner_neurons,
+            MutationKind::RemoveInnerNeuron => genome.num_inner_neurons > 0,
+            _ => true,
+        }
+    }
+
+    /// Whether a gene's from end and its to end could be moved to some other neuron, which they
+    /// can't if the neuron they're on is the only one they're allowed on.
+    fn rewirable_ends(genome: &Genome, nnh: &NeuralNetHelper) -> (bool, bool) {
+        (
+            nnh.from_neuron_ids(genome.num_inner_neurons)
+                .nth(1)
+                .is_some(),
+            nnh.to_neuron_ids(genome.num_inner_neurons).nth(1).is_some(),
+        )
+    }
+
+    fn apply_mutation(
+        genome: &mut Genome,
+        kind: MutationKind,
+        props: &MutationProps,
+        nnh: &NeuralNetHelper,
+        innovations: &mut Innovations,
+        rng: &mut impl Rng,
+    ) {
+        // Most mutations work on one gene picked at random from the bunch
+        let idx = rng.gen_range(0..genome.genes.len());
+        let num_inner_neurons = genome.num_inner_neurons;
+
+        match kind {
+            MutationKind::PerturbWeight => {
+                let nudge: f32 = Normal::new(0.0, props.perturb_sd).unwrap().sample(rng);
+                let gene = &mut genome.genes[idx];
+                gene.weight = (gene.weight + nudge).clamp(-4.0, 4.0);
+            }
+            MutationKind::ReplaceWeight => {
+                genome.genes[idx].weight = Genome::random_weight(rng);
+            }
+            MutationKind::Rewire => {
+                let rewire_from = match Evolver::rewirable_ends(genome, nnh) {
+                    (true, true) => rng.gen_bool(0.5),
+                    (can_move_from, _) => can_move_from,
+                };
+                let gene = &mut genome.genes[idx];
+
+                if rewire_from {
+                    gene.from = nnh.random_from_neuron(num_inner_neurons, Some(gene.from), rng);
+                } else {
+                    gene.to = nnh.random_to_neuron(num_inner_neurons, Some(gene.to), rng);
+                }
+
+                gene.id = innovations.id(gene.from, gene.to);
+            }
+            MutationKind::DuplicateGene => {
+                // The copy connects the same neurons, so it has the same id as the original
+                let mut copy = genome.genes[idx].clone();
+                copy.id = innovations.id(copy.from, copy.to);
+                genome.genes.push(copy);
+            }
+            MutationKind::ToggleGene => {
+                genome.genes[idx].enabled = !genome.genes[idx].enabled;
+            }
+            MutationKind::AddGene => {
+                genome.genes.push(Genome::random_gene(
+                    num_inner_neurons,
+                    nnh,
+                    innovations,
+                    rng,
+                ));
+            }
+            MutationKind::RemoveGene => {
+                genome.genes.remove(idx);
+            }
+            MutationKind::AddInnerNeuron => {
+                Evolver::add_inner_neuron(genome, idx, props, nnh, innovations)
+            }
+            MutationKind::RemoveInnerNeuron => {
+                Evolver::remove_inner_neuron(genome, nnh, innovations, rng)
+            }
+            MutationKind::PerturbBias => {
+                let nudge: f32 = Normal::new(0.0, props.perturb_sd).unwrap().sample(rng);
+                let neuron = Evolver::random_neuron(genome, nnh, rng);
+                neuron.bias = (neuron.bias + nudge).clamp(-4.0, 4.0);
+            }
+            MutationKind::ChangeActivation => {
+                let neuron = Evolver::random_neuron(genome, nnh, rng);
+                let others: Vec<Activation> = Activation::iter()
+                    .filter(|activation| *activation != neuron.activation)
+                    .collect();
+                neuron.activation = others[rng.gen_range(0..others.len())];
+            }
+        }
+    }
+
+    /// One of the genome's inner or output neurons, picked at random.
+    fn random_neuron<'a>(
+        genome: &'a mut Genome,
+        nnh: &NeuralNetHelper,
+        rng: &mut impl Rng,
+    ) -> &'a mut NeuronGene {
+        let ids: Vec<usize> = nnh.biased_neuron_ids(genome.num_inner_neurons).collect();
+        let id = ids[rng.gen_range(0..ids.len())];
+
+        genome.neurons.entry(id).or_default()
+    }
+
+    /// Grows the genome by one inner neuron. If there's room for another gene, the new neuron is
+    /// spliced into the gene at idx, so a -> b becomes a -> new -> b, to give it a chance of
+    /// mattering straight away.
+    fn add_inner_neuron(
+        genome: &mut Genome,
+        idx: usize,
+        props: &MutationProps,
+        nnh: &NeuralNetHelper,
+        innovations: &mut Innovations,
+    ) {
+        let new_neuron_id = nnh
+            .inner_neuron_ids(genome.num_inner_neurons + 1)
+            .last()
+            .unwrap();
+
+        genome.num_inner_neurons += 1;
+        genome.neurons.insert(new_neuron_id, NeuronGene::default());
+
+        if genome.genes.len() >= props.max_genome_size {
+            return;
+        }
+
+        let gene = &mut genome.genes[idx];
+        let spliced = Gene {
+            id: innovations.id(new_neuron_id, gene.to),
+            from: new_neuron_id,
+            to: gene.to,
+            weight: gene.weight,
+            enabled: true,
+        };
+
+        gene.to = new_neuron_id;
+        gene.id = innovations.id(gene.from, gene.to);
+        gene.weight = 1.0;
+        genome.genes.push(spliced);
+    }
+
+    /// Shrinks the genome by its highest numbered inner neuron. Genes that touched that neuron
+    /// are reconnected elsewhere rather than dropped.
+    fn remove_inner_neuron(
+        genome: &mut Genome,
+        nnh: &NeuralNetHelper,
+        innovations: &mut Innovations,
+        rng: &mut impl Rng,
+    ) {
+        let removed_neuron_id = nnh
+            .inner_neuron_ids(genome.num_inner_neurons)
+            .last()
+            .unwrap();
+
+        genome.num_inner_neurons -= 1;
+        genome.neurons.remove(&removed_neuron_id);
+        let num_inner_neurons = genome.num_inner_neurons;
+
+        for gene in genome.genes.iter_mut() {
+            if gene.from == removed_neuron_id {
+                gene.from = nnh.random_from_neuron(num_inner_neurons, None, rng);
+            }
+
+            if gene.to == removed_neuron_id {
+                gene.to = nnh.random_to_neuron(num_inner_neurons, None, rng);
+            }
+
+            gene.id = innovations.id(gene.from, gene.to);
+        }
     }
 }
 
@@ -62,25 +578,53 @@ impl Evolver {
 mod test {
 
     use super::*;
+    use rand::{thread_rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    fn genome(
+        nnh: &NeuralNetHelper,
+        innovations: &mut Innovations,
+        size: usize,
+        num_inner_neurons: usize,
+    ) -> Genome {
+        Genome::new(
+            GenomeProps {
+                neural_net_helper: nnh,
+                size,
+                num_inner_neurons,
+                mutation_rate: 0.1,
+            },
+            innovations,
+            &mut thread_rng(),
+        )
+    }
+
+    fn mutation_props() -> MutationProps {
+        MutationProps {
+            max_genome_size: 20,
+            max_inner_neurons: 5,
+            ..MutationProps::default()
+        }
+    }
 
     #[test]
     fn it_mates_genomes() {
-        let nnh = NeuralNetHelper::new(0);
-
-        let g1 = Genome::new(GenomeProps {
-            neural_net_helper: &nnh,
-            size: 10,
-        });
+        let nnh = NeuralNetHelper::new();
+        let mut innovations = Innovations::new();
 
-        let g2 = Genome::new(GenomeProps {
-            neural_net_helper: &nnh,
-            size: 10,
-        });
+        let g1 = genome(&nnh, &mut innovations, 10, 0);
+        let g2 = genome(&nnh, &mut innovations, 10, 0);
 
-        let g = Evolver::mate(&g1, &g2, &nnh);
+        let g = Evolver::mate(
+            &g1,
+            &g2,
+            &CrossoverProps::default(),
+            &nnh,
+            &mut thread_rng(),
+        );
 
         assert_eq!(g.genes.len(), g1.genes.len());
-        assert!(g.ordered_gene_indices.len() > 0);
+        assert!(!g.ordered_gene_indices.is_empty());
 
         let mut has_some_different = false;
 
@@ -94,33 +638,396 @@ mod test {
         assert!(has_some_different);
     }
 
+    #[test]
+    fn it_mates_genomes_of_different_shapes() {
+        let nnh = NeuralNetHelper::new();
+        let mut innovations = Innovations::new();
+
+        let g1 = genome(&nnh, &mut innovations, 4, 1);
+        let g2 = genome(&nnh, &mut innovations, 10, 3);
+
+        let g = Evolver::mate(
+            &g1,
+            &g2,
+            &CrossoverProps::default(),
+            &nnh,
+            &mut thread_rng(),
+        );
+
+        assert!(g.genes.len() >= 4 && g.genes.len() <= 10);
+        assert_eq!(g.num_inner_neurons, 3);
+    }
+
+    #[test]
+    fn every_crossover_makes_a_valid_child() {
+        let nnh = NeuralNetHelper::new();
+        let mut innovations = Innovations::new();
+        let mut rng = thread_rng();
+
+        for strategy in [
+            Crossover::SinglePoint,
+            Crossover::KPoint,
+            Crossover::Uniform,
+            Crossover::GeneId,
+        ] {
+            let props = CrossoverProps {
+                strategy,
+                points: 3,
+            };
+
+            for _ in 0..50 {
+                let g1 = genome(
+                    &nnh,
+                    &mut innovations,
+                    rng.gen_range(1..15),
+                    rng.gen_range(0..4),
+                );
+                let g2 = genome(
+                    &nnh,
+                    &mut innovations,
+                    rng.gen_range(1..15),
+                    rng.gen_range(0..4),
+                );
+
+                let child = Evolver::mate(&g1, &g2, &props, &nnh, &mut rng);
+
+                assert!(!child.genes.is_empty());
+                assert!(child.genes.len() <= g1.genes.len().max(g2.genes.len()));
+
+                // Every gene came from one of the parents
+                for gene in &child.genes {
+                    assert!(g1.genes.iter().chain(&g2.genes).any(|g| g.id == gene.id
+                        && g.from == gene.from
+                        && g.to == gene.to
+                        && g.weight == gene.weight));
+                }
+
+                let mut recomputed = child.clone();
+                recomputed.recompute_ordered_gene_indices(&nnh);
+                assert_eq!(recomputed.ordered_gene_indices, child.ordered_gene_indices);
+            }
+        }
+    }
+
+    #[test]
+    fn gene_id_crossover_lines_up_genes() {
+        let nnh = NeuralNetHelper::new();
+        let mut innovations = Innovations::new();
+
+        let g1 = genome(&nnh, &mut innovations, 10, 0);
+        let mut g2 = g1.clone();
+
+        for gene in g2.genes.iter_mut() {
+            gene.weight = -gene.weight;
+        }
+
+        // Same ids on both sides, so the child has exactly one of each
+        let child = Evolver::mate(
+            &g1,
+            &g2,
+            &CrossoverProps {
+                strategy: Crossover::GeneId,
+                points: 0,
+            },
+            &nnh,
+            &mut thread_rng(),
+        );
+
+        let mut parent_ids: Vec<usize> = g1.genes.iter().map(|g| g.id).collect();
+        parent_ids.sort();
+
+        let ids: Vec<usize> = child.genes.iter().map(|g| g.id).collect();
+        assert_eq!(ids, parent_ids);
+    }
+
+    #[test]
+    fn gene_id_crossover_keeps_duplicated_genes() {
+        let nnh = NeuralNetHelper::new();
+        let mut innovations = Innovations::new();
+
+        // The first gene twice, like after a DuplicateGene mutation
+        let mut g1 = genome(&nnh, &mut innovations, 3, 0);
+        let mut copy = g1.genes[0].clone();
+        copy.weight = -copy.weight;
+        g1.genes.push(copy);
+
+        let mut g2 = g1.clone();
+        for gene in g2.genes.iter_mut() {
+            gene.weight = -gene.weight;
+        }
+
+        let mut parent_ids: Vec<usize> = g1.genes.iter().map(|g| g.id).collect();
+        parent_ids.sort();
+
+        for _ in 0..20 {
+            let child = Evolver::mate(
+                &g1,
+                &g2,
+                &CrossoverProps {
+                    strategy: Crossover::GeneId,
+                    points: 0,
+                },
+                &nnh,
+                &mut thread_rng(),
+            );
+
+            let mut ids: Vec<usize> = child.genes.iter().map(|g| g.id).collect();
+            ids.sort();
+            assert_eq!(ids, parent_ids);
+        }
+
+        // Trimming a child down to the bigger parent doesn't favour any ids
+        let g3 = genome(&nnh, &mut innovations, 4, 0);
+        let mut kept = BTreeMap::new();
+
+        for _ in 0..200 {
+            let genes = Evolver::gene_id_crossover(&g1, &g3, &mut thread_rng());
+            assert!(genes.len() <= 4);
+
+            for gene in genes {
+                *kept.entry(gene.id).or_insert(0) += 1;
+            }
+        }
+
+        let newest = g3.genes.iter().map(|g| g.id).max().unwrap();
+        assert!(kept.get(&newest).is_some_and(|count| *count > 0));
+    }
+
     #[test]
     fn it_mutates_a_genome() {
-        let nnh = NeuralNetHelper::new(0);
+        let nnh = NeuralNetHelper::new();
+        let mut innovations = Innovations::new();
 
-        let mut genome = Genome::new(GenomeProps {
-            neural_net_helper: &nnh,
-            size: 5,
-        });
+        let mut genome = genome(&nnh, &mut innovations, 5, 1);
 
         let before = genome.clone();
 
-        Evolver::mutate(&mut genome, &nnh);
-
-        assert_eq!(before.genes.len(), genome.genes.len());
+        // Not every birth gets a mutation, so keep going until one does
+        while Evolver::mutate(
+            &mut genome,
+            &mutation_props(),
+            &nnh,
+            &mut innovations,
+            &mut thread_rng(),
+        ) == 0
+        {}
 
-        let mut has_diff_gene = false;
+        // Either the shape changed, or some gene or neuron did
+        let mut has_diff = before.genes.len() != genome.genes.len()
+            || before.num_inner_neurons != genome.num_inner_neurons
+            || before.neurons != genome.neurons;
 
-        // There should be some kind of difference in the genome
-        for i in 0..genome.genes.len() {
-            let b = &before.genes[i];
-            let a = &genome.genes[i];
-            if a.from != b.from || a.to != b.to || a.weight != b.weight {
-                has_diff_gene = true;
+        for (a, b) in genome.genes.iter().zip(&before.genes) {
+            if a.from != b.from || a.to != b.to || a.weight != b.weight || a.enabled != b.enabled {
+                has_diff = true;
                 break;
             }
         }
 
-        assert!(has_diff_gene);
+        assert!(has_diff);
+    }
+
+    #[test]
+    fn mutation_stays_within_limits() {
+        let nnh = NeuralNetHelper::new();
+        let mut innovations = Innovations::new();
+        let props = mutation_props();
+        let mut rng = thread_rng();
+
+        let mut genome = genome(&nnh, &mut innovations, 5, 1);
+
+        for _ in 0..1000 {
+            Evolver::mutate(&mut genome, &props, &nnh, &mut innovations, &mut rng);
+
+            assert!(!genome.genes.is_empty());
+            assert!(genome.genes.len() <= props.max_genome_size);
+            assert!(genome.num_inner_neurons <= props.max_inner_neurons);
+
+            let inner: Vec<usize> = nnh.inner_neuron_ids(genome.num_inner_neurons).collect();
+
+            for gene in &genome.genes {
+                assert!(nnh.input_neurons.contains_key(&gene.from) || inner.contains(&gene.from));
+                assert!(nnh.output_neurons.contains_key(&gene.to) || inner.contains(&gene.to));
+                assert_eq!(gene.id, innovations.id(gene.from, gene.to));
+            }
+        }
+    }
+
+    #[test]
+    fn mutation_rates_pick_the_kinds_and_how_many() {
+        let nnh = NeuralNetHelper::new();
+        let mut innovations = Innovations::new();
+        let mut rng = ChaCha8Rng::seed_from_u64(5);
+
+        let only_perturb = MutationProps {
+            rates: MutationRates {
+                perturb_weight: 5.0,
+                replace_weight: 0.0,
+                rewire: 0.0,
+                duplicate_gene: 0.0,
+                toggle_gene: 0.0,
+                add_gene: 0.0,
+                remove_gene: 0.0,
+                add_inner_neuron: 0.0,
+                remove_inner_neuron: 0.0,
+                perturb_bias: 0.0,
+                change_activation: 0.0,
+            },
+            ..mutation_props()
+        };
+
+        let mut genome = genome(&nnh, &mut innovations, 5, 1);
+        let before = genome.clone();
+        let counts: Vec<usize> = (0..200)
+            .map(|_| Evolver::mutate(&mut genome, &only_perturb, &nnh, &mut innovations, &mut rng))
+            .collect();
+
+        // About 5 a birth, but not always 5
+        let mean = counts.iter().sum::<usize>() as f32 / counts.len() as f32;
+        assert!((4.5..5.5).contains(&mean));
+        assert!(counts.iter().any(|count| *count != 5));
+
+        assert_eq!(genome.genes.len(), before.genes.len());
+        assert_eq!(genome.num_inner_neurons, before.num_inner_neurons);
+
+        for (a, b) in genome.genes.iter().zip(&before.genes) {
+            assert_eq!((a.from, a.to, a.enabled), (b.from, b.to, b.enabled));
+            assert!(a.weight.abs() <= 4.0);
+        }
+
+        // Nothing to do when every kind is switched off
+        let nothing = MutationProps {
+            rates: MutationRates {
+                perturb_weight: 0.0,
+                ..only_perturb.rates.clone()
+            },
+            ..only_perturb
+        };
+
+        assert_eq!(
+            Evolver::mutate(&mut genome, &nothing, &nnh, &mut innovations, &mut rng),
+            0
+        );
+    }
+
+    #[test]
+    fn rewiring_always_moves_the_gene() {
+        let nnh = NeuralNetHelper::new();
+        let mut innovations = Innovations::new();
+        let mut rng = thread_rng();
+        let props = mutation_props();
+
+        // With a single inner neuron, an end on it has to go somewhere outside the inner neurons
+        let mut genome = genome(&nnh, &mut innovations, 1, 1);
+
+        for _ in 0..200 {
+            let before = genome.genes[0].clone();
+
+            assert!(Evolver::can_apply(
+                &genome,
+                MutationKind::Rewire,
+                &props,
+                &nnh
+            ));
+            Evolver::apply_mutation(
+                &mut genome,
+                MutationKind::Rewire,
+                &props,
+                &nnh,
+                &mut innovations,
+                &mut rng,
+            );
+
+            let after = &genome.genes[0];
+            assert_ne!((before.from, before.to), (after.from, after.to));
+        }
+    }
+
+    #[test]
+    fn mutation_rate_drifts_and_is_inherited() {
+        let nnh = NeuralNetHelper::new();
+        let mut innovations = Innovations::new();
+        let mut rng = thread_rng();
+
+        let mut g = genome(&nnh, &mut innovations, 5, 1);
+
+        Evolver::mutate_rate(
+            &mut g,
+            &MutationProps {
+                rate_sd: 0.0,
+                ..mutation_props()
+            },
+            &mut rng,
+        );
+        assert_eq!(g.mutation_rate, 0.1);
+
+        let mut changed = false;
+
+        for _ in 0..50 {
+            Evolver::mutate_rate(&mut g, &mutation_props(), &mut rng);
+            assert!((0.0..=1.0).contains(&g.mutation_rate));
+            changed |= g.mutation_rate != 0.1;
+        }
+
+        assert!(changed);
+
+        let mut g1 = genome(&nnh, &mut innovations, 5, 1);
+        let mut g2 = genome(&nnh, &mut innovations, 5, 1);
+        g1.mutation_rate = 0.2;
+        g2.mutation_rate = 0.4;
+
+        let child = Evolver::mate(&g1, &g2, &CrossoverProps::default(), &nnh, &mut rng);
+        assert!((child.mutation_rate - 0.3).abs() < 1e-6);
+    }
+
+    #[test]
+    fn neurons_mutate_and_are_inherited() {
+        let nnh = NeuralNetHelper::new();
+        let mut innovations = Innovations::new();
+        let mut rng = thread_rng();
+
+        let only_neurons = MutationProps {
+            rates: MutationRates {
+                perturb_weight: 0.0,
+                replace_weight: 0.0,
+                rewire: 0.0,
+                duplicate_gene: 0.0,
+                toggle_gene: 0.0,
+                add_gene: 0.0,
+                remove_gene: 0.0,
+                add_inner_neuron: 0.0,
+                remove_inner_neuron: 0.0,
+                perturb_bias: 10.0,
+                change_activation: 10.0,
+            },
+            ..mutation_props()
+        };
+
+        let mut g1 = genome(&nnh, &mut innovations, 5, 2);
+        let before = g1.clone();
+
+        Evolver::mutate(&mut g1, &only_neurons, &nnh, &mut innovations, &mut rng);
+
+        assert_eq!(g1.neurons.len(), 2 + nnh.output_neurons.len());
+        assert_eq!(
+            g1.neurons.keys().collect::<Vec<_>>(),
+            before.neurons.keys().collect::<Vec<_>>()
+        );
+        assert!(g1.neurons.values().any(|n| n.bias != 0.0));
+        assert!(g1
+            .neurons
+            .values()
+            .any(|n| n.activation != Activation::Tanh));
+        assert!(g1.neurons.values().all(|n| n.bias.abs() <= 4.0));
+
+        // The child gets the extra inner neuron of the bigger parent, and every neuron comes
+        // from one parent or the other
+        let g2 = genome(&nnh, &mut innovations, 5, 3);
+        let child = Evolver::mate(&g1, &g2, &CrossoverProps::default(), &nnh, &mut rng);
+
+        assert_eq!(child.neurons.len(), 3 + nnh.output_neurons.len());
+        for (id, neuron) in &child.neurons {
+            assert!(g1.neurons.get(id) == Some(neuron) || g2.neurons.get(id) == Some(neuron));
+        }
     }
 }
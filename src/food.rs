@@ -0,0 +1,271 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::*;
+
+/// How food grows and spreads. Food acts like plants: every tic it gets a little older and
+/// builds up energy, and once it's old enough it drops seeds around itself that grow into more
+/// food, as long as the area isn't already full of it. That makes food come in patches that
+/// spread, and that can be grazed down to nothing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FoodProps {
+    /// How much energy food builds up each tic, up to 1. Eating food is only worth as much as
+    /// the energy it has.
+    pub growth: f32,
+
+    /// How many tics old food has to be before it starts spreading
+    pub spread_age: usize,
+
+    /// The chance each tic that food old enough to spread drops a seed. 0 means food never
+    /// spreads, and only appears every food_density tics.
+    pub spread_chance: f32,
+
+    /// How far from its parent a seed can land. 1 is the eight spaces around it.
+    pub spread_radius: usize,
+
+    /// How many foods there can be within spread_radius of a space before a seed landing there
+    /// won't take. This is what keeps food from covering the whole world.
+    pub carrying_capacity: usize,
+}
+
+impl Default for FoodProps {
+    fn default() -> Self {
+        Self {
+            growth: 0.01,
+            spread_age: 100,
+            spread_chance: 0.005,
+            spread_radius: 2,
+            carrying_capacity: 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Food {
+    /// How many tics since this food appeared
+    pub age: usize,
+
+    /// How much of food_health and food_hunger eating this gives, from 0 to 1
+    pub energy: f32,
+}
+
+impl Food {
+    /// Food that got to the world on its own, fully grown.
+    pub fn grown() -> Self {
+        Self {
+            age: 0,
+            energy: 1.0,
+        }
+    }
+
+    /// Food that just sprouted from another food's seed.
+    pub fn seedling() -> Self {
+        Self {
+            age: 0,
+            energy: 0.0,
+        }
+    }
+}
+
+/// What's left behind when a lifeform dies. Carrion is worth some of what the lifeform ate over
+/// its life, so a lifeform that's been eating well is worth more dead, and it rots away if nobody
+/// gets to it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CarrionProps {
+    /// Whether lifeforms leave carrion when they die. Off unless it's switched on, so worlds
+    /// without it play out the way they always have.
+    pub enabled: bool,
+
+    /// How much of the energy a lifeform took in from eating over its life its carrion starts out
+    /// with
+    pub nutrition: f32,
+
+    /// How much energy carrion loses each tic. It's gone once it has none left.
+    pub decay: f32,
+}
+
+impl Default for CarrionProps {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            nutrition: 0.5,
+            decay: 0.005,
+        }
+    }
+}
+
+/// Rots all the carrion a little, and takes away what's rotted away completely.
+pub fn decay_carrion(
+    carrion: &mut BTreeMap<(usize, usize), f32>,
+    carrion_index: &mut SpatialIndex<(usize, usize)>,
+    props: &CarrionProps,
+) {
+    carrion.retain(|loc, energy| {
+        *energy -= props.decay;
+
+        if *energy <= 0.0 {
+            carrion_index.remove(*loc, *loc);
+        }
+
+        *energy > 0.0
+    });
+}
+
+/// Ages every food and grows its energy, then has the ones old enough drop seeds. Gives back
+/// where new food sprouted, in the order it did.
+///
+/// A seed lands somewhere within spread_radius of its parent, and sprouts unless it lands on a
+/// wall, on other food, off the edge of a bounded world, or somewhere there are already
+/// carrying_capacity foods within spread_radius.
+pub fn grow_food(
+    food: &mut BTreeMap<(usize, usize), Food>,
+    food_index: &mut SpatialIndex<(usize, usize)>,
+    walls: &BTreeSet<(usize, usize)>,
+    props: &WorldProps,
+    rng: &mut impl Rng,
+) -> Vec<(usize, usize)> {
+    let food_props = &props.food;
+    let mut parents = vec![];
+
+    for (loc, f) in food.iter_mut() {
+        f.age += 1;
+        f.energy = (f.energy + food_props.growth).min(1.0);
+
+        if f.age >= food_props.spread_age {
+            parents.push(*loc);
+        }
+    }
+
+    if food_props.spread_chance <= 0.0 {
+        return vec![];
+    }
+
+    // Within radius + 0.5, so a radius of 1 reaches the corners around a space too
+    let reach = food_props.spread_radius as f32 + 0.5;
+    let radius = food_props.spread_radius as isize;
+    let mut sprouted = vec![];
+
+    for parent in parents {
+        if !rng.gen_bool(food_props.spread_chance.min(1.0) as f64) {
+            continue;
+        }
+
+        let offset = loop {
+            let offset = (
+                rng.gen_range(-radius..=radius),
+                rng.gen_range(-radius..=radius),
+            );
+
+            let (x, y) = (offset.0 as f32, offset.1 as f32);
+            if offset != (0, 0) && x * x + y * y < reach * reach {
+                break offset;
+            }
+        };
+
+        let Some(loc) = props.topology.translate(props.size, &parent, offset) else {
+            continue;
+        };
+
+        if walls.contains(&loc) || food.contains_key(&loc) {
+            continue;
+        }
+
+        if food_index.within(loc, reach).count() >= food_props.carrying_capacity {
+            continue;
+        }
+
+        food.insert(loc, Food::seedling());
+        food_index.insert(loc, loc);
+        sprouted.push(loc);
+    }
+
+    sprouted
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    /// Starts a world of size 20 off with a single seedling and lets it grow for a while.
+    fn grow_from_one_seed(
+        carrying_capacity: usize,
+        walls: &BTreeSet<(usize, usize)>,
+    ) -> BTreeMap<(usize, usize), Food> {
+        let props = WorldProps {
+            size: 20,
+            food: FoodProps {
+                growth: 0.1,
+                spread_age: 5,
+                spread_chance: 0.5,
+                spread_radius: 1,
+                carrying_capacity,
+            },
+            ..WorldProps::default()
+        };
+        let mut rng = ChaCha8Rng::seed_from_u64(4);
+
+        let mut food = BTreeMap::from([((10, 10), Food::seedling())]);
+        let mut food_index = SpatialIndex::new(props.size, props.topology);
+        food_index.insert((10, 10), (10, 10));
+
+        // Too young to spread yet
+        for _ in 0..4 {
+            assert!(grow_food(&mut food, &mut food_index, walls, &props, &mut rng).is_empty());
+        }
+        assert!((food[&(10, 10)].energy - 0.4).abs() < 1e-6);
+
+        for _ in 0..500 {
+            grow_food(&mut food, &mut food_index, walls, &props, &mut rng);
+        }
+
+        assert_eq!(food_index.len(), food.len());
+        assert_eq!(food[&(10, 10)].energy, 1.0);
+
+        for loc in food.keys() {
+            assert!(!walls.contains(loc));
+            assert!(loc.0 <= props.size && loc.1 <= props.size);
+        }
+
+        food
+    }
+
+    #[test]
+    fn spreads_until_the_land_is_full() {
+        let walls = BTreeSet::from([(9, 10), (10, 9)]);
+
+        // With room for 9 around every space nothing holds it back, except the walls
+        let crowded = grow_from_one_seed(9, &walls);
+        assert_eq!(crowded.len(), 21 * 21 - walls.len());
+
+        let sparse = grow_from_one_seed(4, &walls).len();
+        let sparser = grow_from_one_seed(2, &walls).len();
+        assert!(sparser > 10);
+        assert!(sparser < sparse && sparse < crowded.len());
+    }
+
+    #[test]
+    fn carrion_rots_away() {
+        let props = CarrionProps {
+            decay: 0.25,
+            ..CarrionProps::default()
+        };
+        let mut carrion = BTreeMap::from([((1, 1), 0.5), ((2, 2), 1.0)]);
+        let mut carrion_index = SpatialIndex::new(10, Topology::Bounded);
+        carrion_index.insert((1, 1), (1, 1));
+        carrion_index.insert((2, 2), (2, 2));
+
+        decay_carrion(&mut carrion, &mut carrion_index, &props);
+        assert_eq!(carrion, BTreeMap::from([((1, 1), 0.25), ((2, 2), 0.75)]));
+
+        decay_carrion(&mut carrion, &mut carrion_index, &props);
+        assert_eq!(carrion, BTreeMap::from([((2, 2), 0.5)]));
+        assert_eq!(carrion_index.len(), 1);
+        assert!(carrion_index.at((1, 1)).is_empty());
+    }
+}
@@ -1,14 +1,67 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use crate::*;
-use rand::{thread_rng, Rng};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Gene {
     pub id: usize,
     pub from: usize,
     pub to: usize,
     pub weight: f32, // (-4.0 - 4.0)
+
+    /// Disabled genes stay in the genome, so they can be inherited and switched back on by a
+    /// later mutation, but they don't connect anything in the neural net.
+    pub enabled: bool,
+}
+
+/// Hands out gene ids, one for each pair of neurons a gene can connect. The first gene anywhere
+/// in the world to connect two neurons gets a new id, and every gene that connects the same two
+/// after that gets the same one, whichever genome it's in. That way gene id crossover lines up
+/// genes that really do the same thing, like NEAT's innovation numbers.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(
+    from = "Vec<((usize, usize), usize)>",
+    into = "Vec<((usize, usize), usize)>"
+)]
+pub struct Innovations {
+    ids: BTreeMap<(usize, usize), usize>,
+}
+
+impl Innovations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The id of a gene connecting from to to, handing out the next one if nothing has
+    /// connected them yet.
+    pub fn id(&mut self, from: usize, to: usize) -> usize {
+        let next = self.ids.len();
+        *self.ids.entry((from, to)).or_insert(next)
+    }
+}
+
+// JSON can't have tuples for keys, so these get saved as a list of pairs
+impl From<Vec<((usize, usize), usize)>> for Innovations {
+    fn from(ids: Vec<((usize, usize), usize)>) -> Self {
+        Self {
+            ids: ids.into_iter().collect(),
+        }
+    }
+}
+
+impl From<Innovations> for Vec<((usize, usize), usize)> {
+    fn from(innovations: Innovations) -> Self {
+        innovations.ids.into_iter().collect()
+    }
+}
+
+/// The heritable settings of one inner or output neuron.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NeuronGene {
+    pub bias: f32,
+    pub activation: Activation,
 }
 
 pub enum GeneType {
@@ -20,6 +73,8 @@ pub enum GeneType {
 #[derive(Debug, Clone)]
 pub struct GenomeProps<'a> {
     pub size: usize,
+    pub num_inner_neurons: usize,
+    pub mutation_rate: f32,
     pub neural_net_helper: &'a NeuralNetHelper,
 }
 
@@ -30,7 +85,7 @@ pub struct GenomeProps<'a> {
 // it hits inifity :)
 type NeuronGraph = HashMap<usize, Vec<usize>>;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Genome {
     /// An unordered unique list of genes representing one of each gene the genome has. Use this
     /// list to modify the genes in a genome, then call genome.recompute_ordered_genes().
@@ -44,30 +99,82 @@ pub struct Genome {
     /// all kinds of language hacks and cumbersome external crates to make that possible. I figure
     /// best to only use those if absolutely necessary, which, in this case, it's not.
     pub ordered_gene_indices: Vec<usize>,
+
+    /// How many inner neurons this genome's neural net has. Genes may only connect the first
+    /// num_inner_neurons inner neurons from the NeuralNetHelper. This is heritable and can be
+    /// changed by mutation.
+    pub num_inner_neurons: usize,
+
+    /// The chance that a child of this genome gets mutated. This is heritable too, and is itself
+    /// nudged every time it's passed on, so the population can evolve how fast it evolves.
+    pub mutation_rate: f32,
+
+    /// The bias and activation function of each of this genome's inner neurons and of every
+    /// output neuron, keyed by neuron id. New genomes start every neuron on tanh with no bias.
+    pub neurons: BTreeMap<usize, NeuronGene>,
 }
 
 impl Genome {
-    pub fn new(props: GenomeProps) -> Self {
+    pub fn new(props: GenomeProps, innovations: &mut Innovations, rng: &mut impl Rng) -> Self {
         let mut genes: Vec<Gene> = vec![];
 
-        for id in 0..props.size {
-            genes.push(Gene {
-                id,
-                from: props.neural_net_helper.random_from_neuron(None),
-                to: props.neural_net_helper.random_to_neuron(None),
-                weight: Genome::random_weight(),
-            });
+        for _ in 0..props.size {
+            genes.push(Genome::random_gene(
+                props.num_inner_neurons,
+                props.neural_net_helper,
+                innovations,
+                rng,
+            ));
         }
 
-        let ordered_gene_indices = compute_ordered_gene_indices(&genes, props.neural_net_helper);
+        let mut genome = Self {
+            genes,
+            ordered_gene_indices: vec![],
+            num_inner_neurons: props.num_inner_neurons,
+            mutation_rate: props.mutation_rate,
+            neurons: props
+                .neural_net_helper
+                .biased_neuron_ids(props.num_inner_neurons)
+                .map(|id| (id, NeuronGene::default()))
+                .collect(),
+        };
+
+        genome.recompute_ordered_gene_indices(props.neural_net_helper);
 
-        Self { genes, ordered_gene_indices }
+        genome
     }
 
     /// After the genome.genes vector has been messed with, call this to rebuild the data
     /// structures necessary to efficiently calculate this genome's neural net probabilities.
     pub fn recompute_ordered_gene_indices(&mut self, nnh: &NeuralNetHelper) {
-        self.ordered_gene_indices = compute_ordered_gene_indices(&self.genes, nnh);
+        self.ordered_gene_indices =
+            compute_ordered_gene_indices(&self.genes, self.num_inner_neurons, nnh);
+    }
+
+    /// A gene connecting two random neurons that a genome with num_inner_neurons inner neurons
+    /// is allowed to use.
+    pub fn random_gene(
+        num_inner_neurons: usize,
+        nnh: &NeuralNetHelper,
+        innovations: &mut Innovations,
+        rng: &mut impl Rng,
+    ) -> Gene {
+        let from = nnh.random_from_neuron(num_inner_neurons, None, rng);
+        let to = nnh.random_to_neuron(num_inner_neurons, None, rng);
+
+        Gene {
+            id: innovations.id(from, to),
+            from,
+            to,
+            weight: Genome::random_weight(rng),
+            enabled: true,
+        }
+    }
+
+    /// The bias and activation this genome gives a neuron. Neurons it doesn't have an entry for
+    /// are plain tanh.
+    pub fn neuron(&self, id: usize) -> NeuronGene {
+        self.neurons.get(&id).cloned().unwrap_or_default()
     }
 
     /// An InputGene is one that comes from an input. These are where you start when you do a
@@ -83,13 +190,63 @@ impl Genome {
             }
         }
 
-        return GeneType::OutputGene;
+        GeneType::OutputGene
     }
 
-    pub fn random_weight() -> f32 {
-        thread_rng().gen_range(-4.0..=4.0)
+    pub fn random_weight(rng: &mut impl Rng) -> f32 {
+        rng.gen_range(-4.0..=4.0)
     }
 
+    /// How different two genomes are. Genes are lined up by the connection they make (from and
+    /// to), so genes that only one of the genomes has count as disjoint, and the rest are
+    /// compared by weight:
+    ///
+    /// disjoint_coefficient * disjoint / longest + weight_coefficient * mean weight difference
+    ///
+    /// Identical genomes are 0 apart. Genomes with no connections in common come out around
+    /// disjoint_coefficient * 2.
+    pub fn distance(&self, other: &Genome, props: &SpeciesProps) -> f32 {
+        let longest = self.genes.len().max(other.genes.len());
+
+        if longest == 0 {
+            return 0.0;
+        }
+
+        let connections = |genome: &Genome| {
+            let mut map: BTreeMap<(usize, usize), Vec<f32>> = BTreeMap::new();
+
+            for gene in &genome.genes {
+                map.entry((gene.from, gene.to)).or_default().push(gene.weight);
+            }
+
+            map
+        };
+
+        let ours = connections(self);
+        let theirs = connections(other);
+
+        let mut matching = 0;
+        let mut weight_difference = 0.0;
+
+        for (connection, weights) in &ours {
+            if let Some(other_weights) = theirs.get(connection) {
+                for (a, b) in weights.iter().zip(other_weights) {
+                    matching += 1;
+                    weight_difference += (a - b).abs();
+                }
+            }
+        }
+
+        let disjoint = self.genes.len() + other.genes.len() - matching * 2;
+        let mean_weight_difference = if matching == 0 {
+            0.0
+        } else {
+            weight_difference / matching as f32
+        };
+
+        props.disjoint_coefficient * disjoint as f32 / longest as f32
+            + props.weight_coefficient * mean_weight_difference
+    }
 }
 
 /// Takes a vector of unique genes, returns a vector of indices of those genes in the correct order
@@ -99,7 +256,11 @@ impl Genome {
 /// following the connection of the next neuron, etc, recursively, for a specified maximum
 /// number sf times per gene, in case there is a loop. This is the best way I could think of to
 /// approximate biological neural nets.
-fn compute_ordered_gene_indices(genes: &Vec<Gene>, nnh: &NeuralNetHelper) -> Vec<usize> {
+pub fn compute_ordered_gene_indices(
+    genes: &[Gene],
+    num_inner_neurons: usize,
+    nnh: &NeuralNetHelper,
+) -> Vec<usize> {
     // The neural net can have recursive patterns -- one neuron can be connected to itself,
     // or a group of two can connect back and forth to each other, or more, etc.
     // This number determines how many times a specific gene will be followed, therefore how
@@ -107,53 +268,50 @@ fn compute_ordered_gene_indices(genes: &Vec<Gene>, nnh: &NeuralNetHelper) -> Vec
     //
     // TODO I don't think this is necessary any more, we can make this an arbitrary number.
     // No matter what, all the genes that can be followed will get followed at least once.
-    let max_gene_follows = nnh.inner_neurons.len() + 2;
+    let max_gene_follows = num_inner_neurons + 2;
 
     let mut neuron_graph: NeuronGraph = HashMap::new();
     let mut inputs: Vec<usize> = vec![];
     for (idx, gene) in genes.iter().enumerate() {
-        if let GeneType::InputGene = Genome::classify_gene(nnh, &gene) {
-            inputs.push(gene.from);
+        if !gene.enabled {
+            continue;
         }
 
-        neuron_graph.entry(gene.from).or_insert(vec![]).push(idx);
+        if let GeneType::InputGene = Genome::classify_gene(nnh, gene) {
+            if !inputs.contains(&gene.from) {
+                inputs.push(gene.from);
+            }
+        }
+
+        neuron_graph.entry(gene.from).or_default().push(idx);
     }
 
-    // gene id, number of times seen
+    // gene index, number of times followed
     let mut follow_count: HashMap<usize, usize> = HashMap::new();
 
     let mut ordered_gene_indices: Vec<usize> = vec![];
 
-    // * Append all genes from each input_id to ordered_genes, incrementing follow_count
-    // for each TO.
+    // * Append all genes from each input_id to ordered_genes.
     for input_id in inputs {
-        for gene_idx in &neuron_graph[&input_id] {
-            ordered_gene_indices.push(*gene_idx);
-        }
+        ordered_gene_indices.extend(&neuron_graph[&input_id]);
     }
 
-    // * Then go through each member of ordered_genes, append all genes for each FROM
-    // to ordered genes, again incrementing follow_count for each.
-    // * If at any time follow_count is above the limit, don't add that gene again.
+    // * Then go through each member of ordered_genes, append all genes leaving its TO neuron
+    // to ordered genes, incrementing follow_count for the gene that was followed.
+    // * If at any time follow_count is at the limit, don't follow that gene again.
     // * Do this until the loop iterated the same number of times as the length of
     // ordered_genes, noting that that length will be growing as the loop proceeds.
     let mut index = 0;
     while index < ordered_gene_indices.len() {
-        // let gene = &ordered_gene_indices[index];
-        let gene = &genes[ordered_gene_indices[index]];
+        let gene_idx = ordered_gene_indices[index];
+        let count = follow_count.entry(gene_idx).or_insert(0);
+
         // only if it hasn't exceeded its follow_count
-        if let Some(count) = follow_count.get_mut(&gene.id) {
-            if *count < max_gene_follows {
-                for gene_idx in &neuron_graph[&gene.from] {
-                    ordered_gene_indices.push(*gene_idx);
-                }
+        if *count < max_gene_follows {
+            *count += 1;
 
-                *count += 1;
-            }
-        } else {
-            follow_count.insert(gene.id, 1);
-            for gene_idx in &neuron_graph[&gene.from] {
-                ordered_gene_indices.push(*gene_idx);
+            if let Some(next_gene_indices) = neuron_graph.get(&genes[gene_idx].to) {
+                ordered_gene_indices.extend(next_gene_indices);
             }
         }
 
@@ -166,19 +324,134 @@ fn compute_ordered_gene_indices(genes: &Vec<Gene>, nnh: &NeuralNetHelper) -> Vec
 #[cfg(test)]
 mod test {
     use super::*;
+    use rand::{thread_rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
 
     #[test]
     fn makes_stable_ordered_genes() {
-        let nnh = NeuralNetHelper::new(0);
+        let nnh = NeuralNetHelper::new();
 
-        let g1 = Genome::new(GenomeProps {
-            neural_net_helper: &nnh,
-            size: 10,
-        });
+        let g1 = Genome::new(
+            GenomeProps {
+                neural_net_helper: &nnh,
+                size: 10,
+                num_inner_neurons: 0,
+                mutation_rate: 0.1,
+            },
+            &mut Innovations::new(),
+            &mut thread_rng(),
+        );
 
         let mut g2 = g1.clone();
         g2.recompute_ordered_gene_indices(&nnh);
 
         assert_eq!(g1.ordered_gene_indices, g2.ordered_gene_indices);
     }
+
+    #[test]
+    fn genes_connecting_the_same_neurons_share_an_id() {
+        let nnh = NeuralNetHelper::new();
+        let mut innovations = Innovations::new();
+        let props = GenomeProps {
+            neural_net_helper: &nnh,
+            size: 50,
+            num_inner_neurons: 0,
+            mutation_rate: 0.1,
+        };
+
+        let g1 = Genome::new(
+            props.clone(),
+            &mut innovations,
+            &mut ChaCha8Rng::seed_from_u64(1),
+        );
+        let g2 = Genome::new(props, &mut innovations, &mut ChaCha8Rng::seed_from_u64(2));
+
+        let mut shared = 0;
+
+        for a in &g1.genes {
+            for b in &g2.genes {
+                if (a.from, a.to) == (b.from, b.to) {
+                    assert_eq!(a.id, b.id);
+                    shared += 1;
+                } else {
+                    assert_ne!(a.id, b.id);
+                }
+            }
+        }
+
+        assert!(shared > 0);
+    }
+
+    #[test]
+    fn follows_genes_through_inner_neurons() {
+        let nnh = NeuralNetHelper::new();
+
+        // input 100 -> inner 200 -> output 300
+        let mut genome = Genome {
+            genes: vec![
+                Gene {
+                    id: 0,
+                    from: 200,
+                    to: 300,
+                    weight: 1.0,
+                    enabled: true,
+                },
+                Gene {
+                    id: 1,
+                    from: 100,
+                    to: 200,
+                    weight: 1.0,
+                    enabled: true,
+                },
+            ],
+            ordered_gene_indices: vec![],
+            num_inner_neurons: 1,
+            mutation_rate: 0.1,
+            neurons: BTreeMap::new(),
+        };
+
+        genome.recompute_ordered_gene_indices(&nnh);
+
+        assert_eq!(genome.ordered_gene_indices, vec![1, 0]);
+
+        // Switching off the gene from the input cuts off everything downstream of it
+        genome.genes[1].enabled = false;
+        genome.recompute_ordered_gene_indices(&nnh);
+
+        assert!(genome.ordered_gene_indices.is_empty());
+    }
+
+    #[test]
+    fn measures_distance_between_genomes() {
+        let gene = |id, from, to, weight| Gene {
+            id,
+            from,
+            to,
+            weight,
+            enabled: true,
+        };
+
+        let genome = |genes| Genome {
+            genes,
+            ordered_gene_indices: vec![],
+            num_inner_neurons: 1,
+            mutation_rate: 0.1,
+            neurons: BTreeMap::new(),
+        };
+
+        let props = SpeciesProps::default();
+
+        let a = genome(vec![gene(0, 100, 200, 1.0), gene(1, 200, 300, 1.0)]);
+        let b = genome(vec![gene(0, 100, 200, 2.0), gene(1, 200, 300, 1.0)]);
+        let c = genome(vec![gene(0, 100, 200, 1.0), gene(1, 101, 301, 1.0)]);
+
+        assert_eq!(a.distance(&a, &props), 0.0);
+
+        // Same connections, weights 0.5 apart on average
+        assert!((a.distance(&b, &props) - 0.4 * 0.5).abs() < 1e-6);
+
+        // One connection in common, one each that the other doesn't have
+        assert!((a.distance(&c, &props) - 1.0).abs() < 1e-6);
+        assert_eq!(a.distance(&c, &props), c.distance(&a, &props));
+    }
 }
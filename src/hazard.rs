@@ -0,0 +1,297 @@
+use std::collections::BTreeSet;
+
+use clap::ValueEnum;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::*;
+
+/// How a danger gets around.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum HazardBehavior {
+    /// Steps towards the closest lifeform
+    #[default]
+    Hunter,
+
+    /// Steps in a random direction
+    Wanderer,
+
+    /// Walks its path from one waypoint to the next, over and over
+    Patroller,
+
+    /// Never moves, like a patch of radioactive ground
+    Stationary,
+}
+
+/// How a danger's damage falls off with distance from it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Falloff {
+    /// Radioactive: damage over the square of the distance, everywhere in the world
+    #[default]
+    InverseSquare,
+
+    /// All of the damage right on top of it, down to nothing at radius
+    Linear,
+
+    /// The same damage everywhere within radius, and none outside it
+    Flat,
+}
+
+/// One of the dangers in a world. The delay and damage are the world's danger_delay and
+/// danger_damage unless they're given here.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HazardProps {
+    pub behavior: HazardBehavior,
+
+    /// Where it starts out. Somewhere random if this isn't given.
+    pub start: Option<(usize, usize)>,
+
+    /// After how many tics it moves a space
+    pub delay: Option<usize>,
+    pub damage: Option<f32>,
+    pub falloff: Falloff,
+
+    /// How far the damage reaches, for the falloffs that stop somewhere
+    pub radius: f32,
+
+    /// The waypoints a patroller walks between. A patroller without any gets a few random ones.
+    pub path: Vec<(usize, usize)>,
+}
+
+impl Default for HazardProps {
+    fn default() -> Self {
+        Self {
+            behavior: HazardBehavior::Hunter,
+            start: None,
+            delay: None,
+            damage: None,
+            falloff: Falloff::InverseSquare,
+            radius: 3.0,
+            path: vec![],
+        }
+    }
+}
+
+/// A danger in the world, see HazardProps.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Hazard {
+    pub behavior: HazardBehavior,
+    pub location: (usize, usize),
+    pub delay: usize,
+    pub damage: f32,
+    pub falloff: Falloff,
+    pub radius: f32,
+    pub path: Vec<(usize, usize)>,
+
+    /// Which waypoint on the path a patroller is heading for
+    pub waypoint: usize,
+}
+
+impl Hazard {
+    /// Makes the danger props describe. The world gives it a location when props doesn't have a
+    /// start, and a path when it's a patroller without one.
+    pub fn new(
+        props: &HazardProps,
+        world_props: &WorldProps,
+        location: (usize, usize),
+        path: Vec<(usize, usize)>,
+    ) -> Self {
+        Self {
+            behavior: props.behavior,
+            location,
+            delay: props.delay.unwrap_or(world_props.danger_delay),
+            damage: props.damage.unwrap_or(world_props.danger_damage),
+            falloff: props.falloff,
+            radius: props.radius,
+            path,
+            waypoint: 0,
+        }
+    }
+
+    /// How much health a lifeform at loc loses to this danger each tic.
+    pub fn damage_at(&self, topology: Topology, size: usize, loc: &(usize, usize)) -> f32 {
+        let distance = topology.dist_abs(size, loc, &self.location);
+
+        match self.falloff {
+            Falloff::InverseSquare => self.damage / distance.powi(2),
+            Falloff::Linear => self.damage * (1.0 - distance / self.radius).max(0.0),
+            Falloff::Flat if distance <= self.radius => self.damage,
+            Falloff::Flat => 0.0,
+        }
+    }
+
+    /// Moves the danger a space if it's been delay tics, the way its behavior says to.
+    /// closest_lf finds where the lifeform closest to a spot is, if there are any.
+    pub fn step(
+        &mut self,
+        tics: usize,
+        topology: Topology,
+        size: usize,
+        walls: &BTreeSet<(usize, usize)>,
+        closest_lf: impl Fn(&(usize, usize)) -> Option<(usize, usize)>,
+        rng: &mut impl Rng,
+    ) {
+        if !tics.is_multiple_of(self.delay) {
+            return;
+        }
+
+        match self.behavior {
+            HazardBehavior::Hunter => {
+                let target = closest_lf(&self.location).unwrap_or((0, 0));
+                move_towards(topology, size, &mut self.location, &target, walls);
+            }
+            HazardBehavior::Wanderer => {
+                let modifier = [(0, 1), (1, 0), (0, -1), (-1, 0)][rng.gen_range(0..4)];
+
+                if let Some(to) = step_to(topology, size, walls, &self.location, &modifier) {
+                    self.location = to;
+                }
+            }
+            HazardBehavior::Patroller => {
+                if self.path.is_empty() {
+                    return;
+                }
+
+                if self.location == self.path[self.waypoint] {
+                    self.waypoint = (self.waypoint + 1) % self.path.len();
+                }
+
+                let target = self.path[self.waypoint];
+                move_towards(topology, size, &mut self.location, &target, walls);
+            }
+            HazardBehavior::Stationary => {}
+        }
+    }
+
+    /// How the danger is drawn in the world view.
+    pub fn glyph(&self) -> &'static str {
+        match self.behavior {
+            HazardBehavior::Hunter => "☢",
+            HazardBehavior::Wanderer => "☣",
+            HazardBehavior::Patroller => "☠",
+            HazardBehavior::Stationary => "☼",
+        }
+    }
+
+    /// A number for each behavior, so lifeforms can sense what kind of danger is nearest. The
+    /// more it moves with purpose, the higher.
+    pub fn menace(&self) -> f32 {
+        match self.behavior {
+            HazardBehavior::Stationary => 0.0,
+            HazardBehavior::Wanderer => 1.0 / 3.0,
+            HazardBehavior::Patroller => 2.0 / 3.0,
+            HazardBehavior::Hunter => 1.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    fn hazard(props: HazardProps, location: (usize, usize)) -> Hazard {
+        let path = props.path.clone();
+        let world_props = WorldProps {
+            danger_delay: 1,
+            danger_damage: 0.5,
+            ..WorldProps::default()
+        };
+
+        Hazard::new(&props, &world_props, location, path)
+    }
+
+    #[test]
+    fn damage_falls_off_the_way_its_told() {
+        let at = |falloff, loc| {
+            let props = HazardProps {
+                falloff,
+                radius: 4.0,
+                ..HazardProps::default()
+            };
+            hazard(props, (10, 10)).damage_at(Topology::Bounded, 20, &loc)
+        };
+
+        assert_eq!(at(Falloff::InverseSquare, (12, 10)), 0.125);
+        assert_eq!(at(Falloff::Linear, (12, 10)), 0.25);
+        assert_eq!(at(Falloff::Linear, (15, 10)), 0.0);
+        assert_eq!(at(Falloff::Flat, (14, 10)), 0.5);
+        assert_eq!(at(Falloff::Flat, (15, 10)), 0.0);
+    }
+
+    #[test]
+    fn each_behavior_gets_around_its_own_way() {
+        let mut rng = ChaCha8Rng::seed_from_u64(2);
+        let walls = BTreeSet::new();
+        let mut step = |hazard: &mut Hazard, tics| {
+            hazard.step(
+                tics,
+                Topology::Bounded,
+                20,
+                &walls,
+                |_: &(usize, usize)| Some((15, 10)),
+                &mut rng,
+            )
+        };
+
+        let mut hunter = hazard(HazardProps::default(), (10, 10));
+        step(&mut hunter, 1);
+        assert_eq!(hunter.location, (11, 10));
+
+        let mut stationary = hazard(
+            HazardProps {
+                behavior: HazardBehavior::Stationary,
+                ..HazardProps::default()
+            },
+            (10, 10),
+        );
+        step(&mut stationary, 1);
+        assert_eq!(stationary.location, (10, 10));
+
+        let mut wanderer = hazard(
+            HazardProps {
+                behavior: HazardBehavior::Wanderer,
+                ..HazardProps::default()
+            },
+            (10, 10),
+        );
+        step(&mut wanderer, 1);
+        assert_eq!(
+            Topology::Bounded.dist_abs(20, &wanderer.location, &(10, 10)),
+            1.0
+        );
+
+        // Out to the end of the path, and back to the start
+        let mut patroller = hazard(
+            HazardProps {
+                behavior: HazardBehavior::Patroller,
+                path: vec![(10, 10), (10, 12)],
+                ..HazardProps::default()
+            },
+            (10, 10),
+        );
+        let mut visited = vec![];
+        for tics in 1..=4 {
+            step(&mut patroller, tics);
+            visited.push(patroller.location);
+        }
+        assert_eq!(visited, vec![(10, 11), (10, 12), (10, 11), (10, 10)]);
+
+        // Only moves every delay tics
+        let mut slow = hazard(
+            HazardProps {
+                delay: Some(5),
+                ..HazardProps::default()
+            },
+            (10, 10),
+        );
+        step(&mut slow, 4);
+        assert_eq!(slow.location, (10, 10));
+        step(&mut slow, 5);
+        assert_eq!(slow.location, (11, 10));
+    }
+}
@@ -7,6 +7,16 @@ pub mod ui;
 pub mod util;
 pub mod direction;
 pub mod cli;
+pub mod snapshot;
+pub mod stats;
+pub mod lineage;
+pub mod config;
+pub mod species;
+pub mod program;
+pub mod spatial;
+pub mod terrain;
+pub mod food;
+pub mod hazard;
 
 pub use world::*;
 pub use neural_net::*;
@@ -17,3 +27,13 @@ pub use ui::*;
 pub use util::*;
 pub use direction::*;
 pub use cli::*;
+pub use snapshot::*;
+pub use stats::*;
+pub use lineage::*;
+pub use config::*;
+pub use species::*;
+pub use program::*;
+pub use spatial::*;
+pub use terrain::*;
+pub use food::*;
+pub use hazard::*;
@@ -1,16 +1,39 @@
 use std::collections::HashMap;
 
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
 use crate::*;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LifeForm {
     pub id: usize,
+
+    /// Who this lifeform split off from or was cloned from. None for lifeforms made with a random
+    /// genome.
+    pub parent_id: Option<usize>,
+
+    /// The second parent, for lifeforms born from mating
+    pub other_parent_id: Option<usize>,
+
+    /// How many ancestors this lifeform has
+    pub generation: usize,
+
+    /// The founder this lifeform descends from, following parent_id. A founder is its own.
+    pub founder_id: usize,
+
+    /// Which species this lifeform was last sorted into. Newborns start out in their parent's
+    /// species until the next clustering.
+    pub species_id: Option<usize>,
     pub health: f32, // 0 - 1
     pub genome: Genome,
     pub neural_net: NeuralNet,
     pub hunger: f32, // 0 - 1
     pub location: (usize, usize),
     pub lifespan: usize, // How many tics this one has lived for
+
+    /// How much energy this one has taken in from eating over its life, see CarrionProps
+    pub energy: f32,
     pub orientation: Direction,
 
     /// This is for the UI, it can be safely removed if the draws change
@@ -18,61 +41,189 @@ pub struct LifeForm {
 }
 
 impl LifeForm {
-    pub fn new(id: usize, genome_size: usize, neural_net_helper: &NeuralNetHelper) -> Self {
-        let neural_net = neural_net_helper.spawn();
-
-        let genome_props = GenomeProps {
-            size: genome_size,
-            neural_net_helper,
-        };
-
-        let genome = Genome::new(genome_props);
+    pub fn new(
+        id: usize,
+        location: (usize, usize),
+        genome_props: GenomeProps,
+        innovations: &mut Innovations,
+        rng: &mut impl Rng,
+    ) -> Self {
+        let neural_net_helper = genome_props.neural_net_helper;
+        let genome = Genome::new(genome_props, innovations, rng);
+        let neural_net = neural_net_helper.spawn(&genome);
 
         Self {
             id,
+            parent_id: None,
+            other_parent_id: None,
+            generation: 0,
+            founder_id: id,
+            species_id: None,
             genome,
             neural_net,
             health: 1.0,
             hunger: 0.0,
             lifespan: 0,
-            location: (id + 10, id + 10),
+            energy: 0.0,
+            location,
             most_recent_output_neuron_values: None,
             orientation: Direction::new(),
         }
     }
 
-    /// Returns a list of probabilities associated with output neuron types.
+    /// Returns a list of probabilities associated with output neuron types, by running the
+    /// program the genome was compiled to (see NeuralProgram).
+    ///
+    /// Each inner neuron's value is saved on the neural net. When recurrent is set, every inner
+    /// neuron starts the next run with that value already in its sum, which gives the lifeform
+    /// some short term memory. Without it, every run starts from scratch.
+    pub fn run_neural_net(&mut self, recurrent: bool) -> Vec<(OutputNeuronType, f32)> {
+        self.neural_net.run(recurrent)
+    }
+
+    /// How run_neural_net used to work before genomes were compiled, kept around to check the
+    /// compiled programs against and to benchmark them with.
     /// This is the function that consumes the pre-built vector that signifies the order of genes /
     /// the number of times to follow each gene. It consumes that and does the relevant math to
     /// compute the final values of the output neurons.
-    pub fn run_neural_net(&self, nnh: &NeuralNetHelper) -> Vec<(OutputNeuronType, f32)> {
+    pub fn run_neural_net_reference(
+        &mut self,
+        nnh: &NeuralNetHelper,
+        recurrent: bool,
+    ) -> Vec<(OutputNeuronType, f32)> {
         // neuron id, running sum
         let mut running_sums: HashMap<usize, f32> = HashMap::new();
 
+        if recurrent {
+            for neuron in self.neural_net.inner_neurons() {
+                running_sums.insert(neuron.id, neuron.value);
+            }
+        }
+
+        let inputs: HashMap<usize, f32> = self
+            .neural_net
+            .input_neurons()
+            .map(|(_, neuron)| (neuron.id, neuron.value))
+            .collect();
+
         // Idea here is to go through each gene in the ordered genes and if there's an entry
-        // in the running sums map, add tanh(sum(inputs)) to the 'to' gene.
+        // in the running sums map, add the 'from' neuron's activated value to the 'to' gene.
+        // See NeuralNet::activate for what each kind of neuron does with its sum.
         for gene_idx in &self.genome.ordered_gene_indices {
             let gene = &self.genome.genes[*gene_idx];
 
             if let NeuronType::InputNeuron = nnh.neuron_type(&gene.from) {
-                running_sums.insert(gene.from, self.neural_net.input_neurons[&gene.from].1.value);
+                running_sums.insert(gene.from, inputs[&gene.from]);
             }
 
             if let Some(sum) = running_sums.get(&gene.from) {
-                *running_sums.entry(gene.to).or_insert(0.0) += sum.tanh() * gene.weight;
+                let value = self.neural_net.activate(gene.from, *sum);
+                *running_sums.entry(gene.to).or_insert(0.0) += value * gene.weight;
             }
         }
 
         let mut final_output_values: Vec<(OutputNeuronType, f32)> = vec![];
 
-        for (neuron_id, sum) in running_sums {
-            if let NeuronType::OutputNeuron = nnh.neuron_type(&neuron_id) {}
-
-            if let Some((neuron_type, _)) = nnh.output_neurons.get(&neuron_id) {
-                final_output_values.push((neuron_type.clone(), sum.tanh()));
+        // Walk the output neurons rather than the running sums so the values always come out in
+        // the same order, which keeps seeded runs reproducible.
+        for (neuron_id, (neuron_type, _)) in &nnh.output_neurons {
+            if let Some(sum) = running_sums.get(neuron_id) {
+                final_output_values
+                    .push((neuron_type.clone(), self.neural_net.activate(*neuron_id, *sum)));
             }
         }
 
+        self.neural_net.program.inner = self
+            .neural_net
+            .inner_neurons()
+            .map(|neuron| match running_sums.get(&neuron.id) {
+                Some(sum) => self.neural_net.activate(neuron.id, *sum),
+                None => 0.0,
+            })
+            .collect();
+
         final_output_values
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::thread_rng;
+    use std::collections::BTreeMap;
+
+    /// input 100 -> inner 200 (identity) -> output 300
+    fn remembering_lifeform(nnh: &NeuralNetHelper) -> LifeForm {
+        let mut lf = LifeForm::new(
+            0,
+            (0, 0),
+            GenomeProps {
+                size: 1,
+                num_inner_neurons: 1,
+                mutation_rate: 0.1,
+                neural_net_helper: nnh,
+            },
+            &mut Innovations::new(),
+            &mut thread_rng(),
+        );
+
+        let gene = |id, from, to| Gene {
+            id,
+            from,
+            to,
+            weight: 1.0,
+            enabled: true,
+        };
+
+        let mut neurons = BTreeMap::new();
+        neurons.insert(
+            200,
+            NeuronGene {
+                bias: 0.0,
+                activation: Activation::Identity,
+            },
+        );
+
+        lf.genome = Genome {
+            genes: vec![gene(0, 100, 200), gene(1, 200, 300)],
+            ordered_gene_indices: vec![],
+            num_inner_neurons: 1,
+            mutation_rate: 0.1,
+            neurons,
+        };
+        lf.genome.recompute_ordered_gene_indices(nnh);
+        lf.neural_net = nnh.spawn(&lf.genome);
+
+        lf
+    }
+
+    fn run_with_input(
+        lf: &mut LifeForm,
+        nnh: &NeuralNetHelper,
+        input: f32,
+        recurrent: bool,
+    ) -> f32 {
+        lf.neural_net.program.inputs[0] = input;
+        let reference = lf.clone().run_neural_net_reference(nnh, recurrent);
+        let outputs = lf.run_neural_net(recurrent);
+        assert_eq!(outputs, reference);
+
+        outputs[0].1
+    }
+
+    #[test]
+    fn recurrent_neurons_remember_the_last_tic() {
+        let nnh = NeuralNetHelper::new();
+        let remembered = 1.0_f32.tanh();
+
+        let mut forgetful = remembering_lifeform(&nnh);
+        run_with_input(&mut forgetful, &nnh, 1.0, false);
+        assert_eq!(forgetful.neural_net.program.inner[0], remembered);
+        assert_eq!(run_with_input(&mut forgetful, &nnh, 0.0, false), 0.0);
+
+        let mut recurrent = remembering_lifeform(&nnh);
+        run_with_input(&mut recurrent, &nnh, 1.0, true);
+        assert_eq!(run_with_input(&mut recurrent, &nnh, 0.0, true), remembered.tanh());
+        assert_eq!(recurrent.neural_net.program.inner[0], remembered);
+    }
+}
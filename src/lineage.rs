@@ -0,0 +1,371 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::*;
+
+/// One lifeform's entry in the family tree.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LineageRecord {
+    pub id: usize,
+
+    /// None for founders, the lifeforms that were made with a random genome
+    pub parent_id: Option<usize>,
+
+    /// The second parent, for lifeforms born from mating. The family tree follows parent_id.
+    pub other_parent_id: Option<usize>,
+    pub generation: usize,
+
+    /// The tic this lifeform was born on
+    pub born: usize,
+
+    /// The tic this lifeform died on, if it has
+    pub died: Option<usize>,
+}
+
+/// Every lifeform that has ever lived in a world, keyed by id. Since ids are never reused, this
+/// is enough to walk from any lifeform back up to the founder it descends from.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Lineage {
+    pub records: BTreeMap<usize, LineageRecord>,
+}
+
+impl Lineage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_birth(&mut self, lf: &LifeForm, tic: usize) {
+        self.records.insert(
+            lf.id,
+            LineageRecord {
+                id: lf.id,
+                parent_id: lf.parent_id,
+                other_parent_id: lf.other_parent_id,
+                generation: lf.generation,
+                born: tic,
+                died: None,
+            },
+        );
+    }
+
+    pub fn record_death(&mut self, id: usize, tic: usize) {
+        if let Some(record) = self.records.get_mut(&id) {
+            record.died = Some(tic);
+        }
+    }
+
+    /// The chain of ids from this lifeform's parent up to its founder, nearest first.
+    pub fn ancestors(&self, id: usize) -> Vec<usize> {
+        let mut ancestors = vec![];
+        let mut current = self.records.get(&id).and_then(|r| r.parent_id);
+
+        while let Some(parent_id) = current {
+            ancestors.push(parent_id);
+            current = self.records.get(&parent_id).and_then(|r| r.parent_id);
+        }
+
+        ancestors
+    }
+
+    /// Which founding lifeform this one descends from. A founder is its own founder.
+    pub fn founder(&self, id: usize) -> usize {
+        self.ancestors(id).last().copied().unwrap_or(id)
+    }
+
+    /// Each lifeform's children, in the order they were born.
+    pub fn children(&self) -> BTreeMap<usize, Vec<usize>> {
+        let mut children: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+
+        for record in self.records.values() {
+            if let Some(parent_id) = record.parent_id {
+                children.entry(parent_id).or_default().push(record.id);
+            }
+        }
+
+        children
+    }
+
+    pub fn founders(&self) -> Vec<usize> {
+        self.records
+            .values()
+            .filter(|r| r.parent_id.is_none())
+            .map(|r| r.id)
+            .collect()
+    }
+
+    /// How many tics this lifeform lived for, counting the ones still alive up to end_tic.
+    fn lifetime(&self, id: usize, end_tic: usize) -> usize {
+        let record = &self.records[&id];
+        record.died.unwrap_or(end_tic) - record.born
+    }
+
+    /// The whole family tree as a Newick string, with every founder hanging off one root.
+    ///
+    /// Branch lengths are in tics. A lifeform with children becomes an internal node at the tic
+    /// it was born, with one tip for its own life (lf<id>, as long as it lived) and one branch
+    /// for each child (as long as it took for that child to come along). A lifeform without
+    /// children is just a tip. That puts every tip at the tic its lifeform died, or at end_tic
+    /// if it's still alive.
+    ///
+    /// Lineages can get thousands of generations deep, so this walks the tree with a stack
+    /// rather than recursing.
+    pub fn to_newick(&self, end_tic: usize) -> String {
+        enum Step {
+            Open(usize, usize),
+            Close(usize, usize),
+            Comma,
+        }
+
+        let children = self.children();
+        let mut newick = String::from("(");
+
+        for (idx, founder) in self.founders().into_iter().enumerate() {
+            if idx > 0 {
+                newick.push(',');
+            }
+
+            let mut stack = vec![Step::Open(founder, 0)];
+
+            while let Some(step) = stack.pop() {
+                match step {
+                    Step::Open(id, parent_born) => {
+                        let born = self.records[&id].born;
+                        let lifetime = self.lifetime(id, end_tic);
+
+                        match children.get(&id) {
+                            None => {
+                                write!(newick, "lf{}:{}", id, born - parent_born + lifetime)
+                                    .unwrap();
+                            }
+                            Some(child_ids) => {
+                                write!(newick, "(lf{}:{}", id, lifetime).unwrap();
+                                stack.push(Step::Close(id, parent_born));
+
+                                for child_id in child_ids.iter().rev() {
+                                    stack.push(Step::Open(*child_id, born));
+                                    stack.push(Step::Comma);
+                                }
+                            }
+                        }
+                    }
+                    Step::Close(id, parent_born) => {
+                        write!(newick, "):{}", self.records[&id].born - parent_born).unwrap();
+                    }
+                    Step::Comma => newick.push(','),
+                }
+            }
+        }
+
+        newick.push_str(");");
+        newick
+    }
+
+    /// The family tree as nested JSON: a list with a node for each founder, where every node is
+    /// `{"id", "generation", "born", "died", "children"}` and children holds the nodes of the
+    /// lifeforms it had.
+    ///
+    /// Like to_newick this walks the tree with a stack. Building the nested nodes and handing
+    /// them to serde would recurse once per generation, both serializing and dropping them.
+    pub fn to_json_tree(&self) -> String {
+        enum Step {
+            Open(usize),
+            Close,
+            Comma,
+        }
+
+        let children = self.children();
+        let mut json = String::from("[");
+        let mut stack = vec![];
+
+        for (idx, founder) in self.founders().into_iter().enumerate().rev() {
+            stack.push(Step::Open(founder));
+            if idx > 0 {
+                stack.push(Step::Comma);
+            }
+        }
+
+        while let Some(step) = stack.pop() {
+            match step {
+                Step::Open(id) => {
+                    let record = &self.records[&id];
+                    let died = match record.died {
+                        Some(died) => died.to_string(),
+                        None => String::from("null"),
+                    };
+
+                    write!(
+                        json,
+                        r#"{{"id":{},"generation":{},"born":{},"died":{},"children":["#,
+                        record.id, record.generation, record.born, died
+                    )
+                    .unwrap();
+                    stack.push(Step::Close);
+
+                    let child_ids = children.get(&id).map(Vec::as_slice).unwrap_or_default();
+
+                    for (idx, child_id) in child_ids.iter().enumerate().rev() {
+                        stack.push(Step::Open(*child_id));
+                        if idx > 0 {
+                            stack.push(Step::Comma);
+                        }
+                    }
+                }
+                Step::Close => json.push_str("]}"),
+                Step::Comma => json.push(','),
+            }
+        }
+
+        json.push(']');
+        json
+    }
+
+    pub fn write_newick(&self, path: &Path, end_tic: usize) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "{}", self.to_newick(end_tic))?;
+        writer.flush()
+    }
+
+    pub fn write_json_tree(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "{}", self.to_json_tree())?;
+        writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::thread_rng;
+
+    #[test]
+    fn walks_back_to_the_founder() {
+        let nnh = NeuralNetHelper::new();
+        let mut lineage = Lineage::new();
+
+        let founder = LifeForm::new(
+            0,
+            (0, 0),
+            GenomeProps {
+                size: 5,
+                num_inner_neurons: 1,
+                mutation_rate: 0.1,
+                neural_net_helper: &nnh,
+            },
+            &mut Innovations::new(),
+            &mut thread_rng(),
+        );
+        lineage.record_birth(&founder, 0);
+
+        let mut child = founder.clone();
+        child.id = 1;
+        child.parent_id = Some(0);
+        child.generation = 1;
+        lineage.record_birth(&child, 10);
+
+        let mut grandchild = child.clone();
+        grandchild.id = 2;
+        grandchild.parent_id = Some(1);
+        grandchild.generation = 2;
+        lineage.record_birth(&grandchild, 20);
+
+        lineage.record_death(0, 15);
+
+        assert_eq!(lineage.ancestors(2), vec![1, 0]);
+        assert_eq!(lineage.founder(2), 0);
+        assert_eq!(lineage.founder(0), 0);
+        assert_eq!(lineage.records[&0].died, Some(15));
+        assert_eq!(lineage.records[&2].generation, 2);
+    }
+
+    fn record(
+        id: usize,
+        parent_id: Option<usize>,
+        born: usize,
+        died: Option<usize>,
+    ) -> LineageRecord {
+        LineageRecord {
+            id,
+            parent_id,
+            other_parent_id: None,
+            generation: 0,
+            born,
+            died,
+        }
+    }
+
+    fn small_lineage() -> Lineage {
+        let mut lineage = Lineage::new();
+
+        // 0 has children 2 and 3, 3 has child 4, 1 never reproduces
+        for r in [
+            record(0, None, 0, Some(50)),
+            record(1, None, 0, Some(20)),
+            record(2, Some(0), 10, None),
+            record(3, Some(0), 30, Some(60)),
+            record(4, Some(3), 40, None),
+        ] {
+            lineage.records.insert(r.id, r);
+        }
+
+        lineage
+    }
+
+    #[test]
+    fn writes_newick() {
+        assert_eq!(
+            small_lineage().to_newick(100),
+            "((lf0:50,lf2:100,(lf3:30,lf4:70):30):0,lf1:20);"
+        );
+    }
+
+    #[test]
+    fn builds_a_json_tree() {
+        let tree: serde_json::Value =
+            serde_json::from_str(&small_lineage().to_json_tree()).unwrap();
+        let ids = |nodes: &serde_json::Value| {
+            nodes
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|node| node["id"].as_u64().unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(ids(&tree), vec![0, 1]);
+        assert_eq!(ids(&tree[0]["children"]), vec![2, 3]);
+        assert_eq!(ids(&tree[0]["children"][1]["children"]), vec![4]);
+        assert_eq!(tree[0]["died"], 50);
+        assert_eq!(tree[0]["children"][0]["died"], serde_json::Value::Null);
+        assert!(tree[1]["children"].as_array().unwrap().is_empty());
+    }
+
+    #[test]
+    fn handles_a_lineage_thousands_of_generations_deep() {
+        const DEPTH: usize = 100_000;
+        let mut lineage = Lineage::new();
+
+        for id in 0..DEPTH {
+            let mut r = record(id, id.checked_sub(1), id, Some(id + 1));
+            r.generation = id;
+            lineage.records.insert(id, r);
+        }
+
+        let json = lineage.to_json_tree();
+        assert!(
+            json.starts_with(r#"[{"id":0,"generation":0,"born":0,"died":1,"children":[{"id":1,"#)
+        );
+        assert!(json.ends_with(&format!("{}]", "]}".repeat(DEPTH))));
+        assert_eq!(json.matches(r#""children":["#).count(), DEPTH);
+
+        let newick = lineage.to_newick(DEPTH);
+        assert_eq!(newick.matches('(').count(), DEPTH);
+        assert!(newick.ends_with(":1):1):0);"));
+    }
+}
@@ -1,5 +1,7 @@
 use std::{
     io,
+    path::Path,
+    process,
     time::{Duration, Instant},
 };
 
@@ -17,43 +19,116 @@ use evolution::*;
 // TODO
 // * Get timestamps for events
 // * Make rel distance go fro -1 to 1 (more sensitivity)
-// * Let food be a thing that, after it gets a certain age, itself splits into multiple of it. That
-//  way it's like plants, getting energy from the ambient system.
 // * Let lifeforms know when they're up against the edge (ie. distance to edge, either distance to
 // every edge or better would be distance to edge _in front_ of the lf)
-// * Should lifeforms leave behind food when they die? This would kind of add a little pressure to
-// atack each other
 
 
 fn main() {
     let args = Args::parse();
 
-    // Size of the world
-    let size = args.size;
+    if let Some(path) = &args.load {
+        let snapshot = match WorldSnapshot::load(path) {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                eprintln!("Could not load {}: {}", path.display(), e);
+                process::exit(1);
+            }
+        };
 
-    let num_inner_neurons = args.num_inner_neurons;
+        let nnh = NeuralNetHelper::new();
+        let world = World::from_snapshot(snapshot, &nnh);
 
-    let nnh = NeuralNetHelper::new(num_inner_neurons);
+        run(world, &args);
+        return;
+    }
 
-    let world_props = WorldProps {
-        size,
-        neural_net_helper: &nnh,
-        num_initial_lifeforms: args.num_initial_lifeforms,
-        genome_size: args.genome_size,
-        mutation_rate: args.mutation_rate,
-        food_density: args.food_density,
-        num_inner_neurons,
-        minimum_number_lifeforms: args.minimum_number_lifeforms,
-        danger_delay: args.danger_delay,
-        danger_damage: args.danger_damage,
+    let world_props = match args.world_props() {
+        Ok(props) => props,
+        Err(e) => {
+            eprintln!("Could not configure the world: {}", e);
+            process::exit(1);
+        }
     };
 
-    let world = World::new(world_props);
+    let nnh = NeuralNetHelper::new();
+
+    let world = World::new(world_props, &nnh);
+
+    run(world, &args);
+}
+
+/// Hands the world off to either the terminal UI or, when asked for, the headless runner.
+fn run(mut world: World, args: &Args) {
+    if args.headless {
+        // clap guarantees tics is given alongside headless
+        run_headless(&mut world, args.tics.unwrap(), args.report_interval);
+    } else {
+        run_app(&mut world, &args.save_file);
+    }
+
+    if let Some(path) = &args.stats_file {
+        if let Err(e) = world.stats.write_to_file(path, args.stats_format) {
+            eprintln!("Could not write stats to {}: {}", path.display(), e);
+        }
+    }
+
+    if let Some(path) = &args.newick_file {
+        if let Err(e) = world.lineage.write_newick(path, world.tics) {
+            eprintln!("Could not write the Newick tree to {}: {}", path.display(), e);
+        }
+    }
+
+    if let Some(path) = &args.tree_file {
+        if let Err(e) = world.lineage.write_json_tree(path) {
+            eprintln!("Could not write the lineage tree to {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Steps the world as fast as it'll go without touching the terminal. Every report_interval tics
+/// a line of stats is printed to stdout, and a summary is printed once all the tics have run.
+fn run_headless(world: &mut World, tics: usize, report_interval: usize) {
+    let start = Instant::now();
+    let mut last_report = Instant::now();
+
+    for tic in 1..=tics {
+        world.step();
+
+        if report_interval > 0 && tic.is_multiple_of(report_interval) {
+            let tics_per_second = report_interval as f64 / last_report.elapsed().as_secs_f64();
+            println!("{} | {:.0} tics/s", stat_line(world), tics_per_second);
+            last_report = Instant::now();
+        }
+    }
+
+    let elapsed = start.elapsed().as_secs_f64();
 
-    run_app(size, world);
+    println!(
+        "Finished {} tics in {:.1}s ({:.0} tics/s)",
+        tics,
+        elapsed,
+        tics as f64 / elapsed
+    );
+    println!("{}", stat_line(world));
 }
 
-fn run_app(size: usize, mut world: World) {
+fn stat_line(world: &World) -> String {
+    let oldest = world.lifeforms.values().map(|lf| lf.lifespan).max().unwrap_or(0);
+
+    format!(
+        "iteration: {} | lifeforms: {} | average age: {:.1} | oldest: {} | food: {} | seed: {}",
+        world.tics,
+        world.lifeforms.len(),
+        world.average_age(),
+        oldest,
+        world.food.len(),
+        world.props().seed
+    )
+}
+
+fn run_app(world: &mut World, save_file: &Path) {
+    let size = world.props().size;
+
     enable_raw_mode().unwrap();
     let mut stdout = io::stdout();
     execute!(stdout, EnterAlternateScreen, EnableMouseCapture).unwrap();
@@ -76,12 +151,20 @@ fn run_app(size: usize, mut world: World) {
     let mut pause_info = 0;
     let mut should_draw = true;
 
+    // Result of the most recent save, shown in the controls panel
+    let mut status: Option<String> = None;
+
+    // Which time series the chart panel is showing
+    let mut chart_series = ChartSeries::Population;
+
     loop {
         let lf = selected_lf_id.and_then(|id| world.lifeforms.get(&id));
 
         if should_draw {
             terminal
-                .draw(|f| ui(f, size, &world, lf, saved_tick_rate))
+                .draw(|f| {
+                    ui(f, size, world, lf, saved_tick_rate, status.as_deref(), chart_series)
+                })
                 .unwrap();
         }
 
@@ -96,19 +179,26 @@ fn run_app(size: usize, mut world: World) {
                 match key.code {
                     KeyCode::Char('q') => break,
                     KeyCode::Char('d') => should_draw = !should_draw,
+                    KeyCode::Char('c') => chart_series = chart_series.next(),
+                    KeyCode::Char('s') => {
+                        status = Some(match world.snapshot().save(save_file) {
+                            Ok(()) => format!("Saved world to {}", save_file.display()),
+                            Err(e) => format!("Could not save to {}: {}", save_file.display(), e),
+                        });
+                    }
                     KeyCode::Char('p') => {
                         if paused {
                             paused = false;
                             saved_tick_rate = pause_info;
                         } else {
                             paused = true;
-                            pause_info = saved_tick_rate.clone();
+                            pause_info = saved_tick_rate;
                             saved_tick_rate = u64::MAX;
                         }
                     }
                     KeyCode::Up => {
-                        if let None = selected_lf_id {
-                            selected_lf_id = world.lifeforms.keys().map(|k| *k).last();
+                        if selected_lf_id.is_none() {
+                            selected_lf_id = world.lifeforms.keys().copied().last();
                         } else if let Some(id) = selected_lf_id {
                             let current_index = world.lifeforms.keys().position(|lid| lid == &id);
 
@@ -117,7 +207,7 @@ fn run_app(size: usize, mut world: World) {
                                     selected_lf_id = None;
                                 } else {
                                     selected_lf_id =
-                                        world.lifeforms.keys().map(|k| *k).nth(current_index - 1)
+                                        world.lifeforms.keys().copied().nth(current_index - 1)
                                 }
                             } else {
                                 selected_lf_id = None;
@@ -125,8 +215,8 @@ fn run_app(size: usize, mut world: World) {
                         }
                     }
                     KeyCode::Down => {
-                        if let None = selected_lf_id {
-                            selected_lf_id = world.lifeforms.keys().map(|k| *k).nth(0);
+                        if selected_lf_id.is_none() {
+                            selected_lf_id = world.lifeforms.keys().copied().next();
                         } else if let Some(id) = selected_lf_id {
                             let current_index = world.lifeforms.keys().position(|lid| lid == &id);
 
@@ -135,14 +225,14 @@ fn run_app(size: usize, mut world: World) {
                                     selected_lf_id = None;
                                 } else {
                                     selected_lf_id =
-                                        world.lifeforms.keys().map(|k| *k).nth(current_index + 1)
+                                        world.lifeforms.keys().copied().nth(current_index + 1)
                                 }
                             } else {
                                 selected_lf_id = None;
                             }
                         }
                     }
-                    KeyCode::Left => saved_tick_rate = saved_tick_rate / 3,
+                    KeyCode::Left => saved_tick_rate /= 3,
                     KeyCode::Right => saved_tick_rate = (saved_tick_rate * 2) + 1,
                     _ => (),
                 };
@@ -151,7 +241,9 @@ fn run_app(size: usize, mut world: World) {
                 let lf = selected_lf_id.and_then(|id| world.lifeforms.get(&id));
 
                 terminal
-                    .draw(|f| ui(f, size, &world, lf, saved_tick_rate))
+                    .draw(|f| {
+                        ui(f, size, world, lf, saved_tick_rate, status.as_deref(), chart_series)
+                    })
                     .unwrap();
             }
         }
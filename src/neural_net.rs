@@ -1,26 +1,47 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
-use rand::{thread_rng, Rng};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
 use strum::IntoEnumIterator;
 use strum_macros::{Display, EnumIter};
 
+use crate::*;
+
+/// Where each kind of neuron's ids start. Every input neuron type gets an id, then inner neurons
+/// and output neurons follow in their own ranges.
+pub const FIRST_INPUT_NEURON: usize = 100;
+pub const FIRST_INNER_NEURON: usize = 200;
+pub const FIRST_OUTPUT_NEURON: usize = 300;
+
+/// Inner neurons are numbered from 200 and output neurons from 300, so this is as many inner
+/// neurons as any one genome can ever grow.
+pub const MAX_INNER_NEURONS: usize = FIRST_OUTPUT_NEURON - FIRST_INNER_NEURON;
+
 /// Builds AND houses data structures that help for speedy neural net related calculations
 /// Meant to be a singleton that itself builds neural nets and houses these helpers.
 /// Must be instantiated to generate data structures.
+/// Every inner neuron a genome could use is registered here. Each genome says how many of them
+/// it actually has, starting from the lowest id, so lineages can grow and shrink their brains.
 #[derive(Debug)]
 pub struct NeuralNetHelper {
-    pub input_neurons: HashMap<usize, (InputNeuronType, InputNeuron)>,
-    pub inner_neurons: HashMap<usize, InnerNeuron>,
-    pub output_neurons: HashMap<usize, (OutputNeuronType, OutputNeuron)>,
+    pub input_neurons: BTreeMap<usize, (InputNeuronType, InputNeuron)>,
+    pub inner_neurons: BTreeMap<usize, InnerNeuron>,
+    pub output_neurons: BTreeMap<usize, (OutputNeuronType, OutputNeuron)>,
 
     neuron_type_map: HashMap<usize, NeuronType>,
 }
 
+impl Default for NeuralNetHelper {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl NeuralNetHelper {
-    pub fn new(num_inner_neurons: usize) -> Self {
-        let mut input_neurons = HashMap::new();
-        let mut output_neurons = HashMap::new();
-        let mut inner_neurons = HashMap::new();
+    pub fn new() -> Self {
+        let mut input_neurons = BTreeMap::new();
+        let mut output_neurons = BTreeMap::new();
+        let mut inner_neurons = BTreeMap::new();
         let mut neuron_type_map = HashMap::new();
 
         // -- Generate Neurons
@@ -28,22 +49,28 @@ impl NeuralNetHelper {
         for (idx, neuron_member) in InputNeuronType::iter().enumerate() {
             // Assuming there'll never be more than 100 input neuron types, we'll do this
             // to assure a different id from the output neurons
-            let id = idx + 100;
+            let id = idx + FIRST_INPUT_NEURON;
             let neuron = InputNeuron { id, value: 0.0 };
             input_neurons.insert(id, (neuron_member, neuron));
             neuron_type_map.insert(id, NeuronType::InputNeuron);
         }
 
-        for idx in 0..num_inner_neurons {
-            let id = idx + 200;
-            let neuron = InnerNeuron { id };
+        for idx in 0..MAX_INNER_NEURONS {
+            let id = idx + FIRST_INNER_NEURON;
+            let neuron = InnerNeuron {
+                id,
+                ..InnerNeuron::default()
+            };
             inner_neurons.insert(id, neuron);
             neuron_type_map.insert(id, NeuronType::InnerNeuron);
         }
 
         for (idx, neuron_member) in OutputNeuronType::iter().enumerate() {
-            let id = idx + 300;
-            let neuron = OutputNeuron { id };
+            let id = idx + FIRST_OUTPUT_NEURON;
+            let neuron = OutputNeuron {
+                id,
+                ..OutputNeuron::default()
+            };
             output_neurons.insert(id, (neuron_member, neuron));
             neuron_type_map.insert(id, NeuronType::OutputNeuron);
         }
@@ -58,58 +85,77 @@ impl NeuralNetHelper {
         }
     }
 
-    /// Spawn a new neural net based off the blueprint that was created at instantiation time.
-    /// Cloning saves compute resources at the expense of memory, which is perfect for us.
-    pub fn spawn(&self) -> NeuralNet {
+    /// Spawn a new neural net for a genome, with the genome's inner neurons, and the biases and
+    /// activations the genome gives its neurons. The genome is compiled into the net's program
+    /// here, since the genome never changes after birth.
+    pub fn spawn(&self, genome: &Genome) -> NeuralNet {
         NeuralNet {
-            input_neurons: self.input_neurons.clone(),
-            inner_neurons: self.inner_neurons.clone(),
-            output_neurons: self.output_neurons.clone(),
+            program: NeuralProgram::compile(genome, self),
         }
     }
 
-    /// Returns a neuron id randomly chosen from input neurons unioned with inner neurons.
-    /// This is all the places where a gene can start from.
-    /// Takes an optional "not" value, which, if supplied, will prevent this from returning
-    /// that value.
-    pub fn random_from_neuron(&self, not_id: Option<usize>) -> usize {
-        let num_neurons = self.input_neurons.len() + self.inner_neurons.len();
-        let idx = thread_rng().gen_range(0..num_neurons);
-
-        let id: usize;
-
-        if idx < self.input_neurons.len() {
-            let ids = &self.input_neurons.keys().map(|k| *k).collect();
-            id = get_id_not_id(ids, idx, not_id);
-        } else {
-            let ids = &self.inner_neurons.keys().map(|k| *k).collect();
-            let index = idx - self.input_neurons.len();
-            id = get_id_not_id(ids, index, not_id);
-        }
+    /// The ids of every neuron a genome with this many inner neurons gives a bias and activation
+    /// to: its inner neurons and all the output neurons.
+    pub fn biased_neuron_ids(&self, num_inner_neurons: usize) -> impl Iterator<Item = usize> + '_ {
+        self.inner_neuron_ids(num_inner_neurons)
+            .chain(self.output_neurons.keys().copied())
+    }
+
+    /// The ids of the first num_inner_neurons inner neurons, which are the ones a genome with
+    /// that many inner neurons can connect.
+    pub fn inner_neuron_ids(&self, num_inner_neurons: usize) -> impl Iterator<Item = usize> + '_ {
+        self.inner_neurons.keys().copied().take(num_inner_neurons)
+    }
+
+    /// The ids of every neuron a gene can start from in a genome with this many inner neurons:
+    /// the input neurons and its inner neurons.
+    pub fn from_neuron_ids(&self, num_inner_neurons: usize) -> impl Iterator<Item = usize> + '_ {
+        self.input_neurons
+            .keys()
+            .copied()
+            .chain(self.inner_neuron_ids(num_inner_neurons))
+    }
 
-        id
+    /// The ids of every neuron a gene can end at, aka go to, in a genome with this many inner
+    /// neurons: its inner neurons and the output neurons.
+    pub fn to_neuron_ids(&self, num_inner_neurons: usize) -> impl Iterator<Item = usize> + '_ {
+        self.biased_neuron_ids(num_inner_neurons)
     }
 
-    /// Returns a neuron id randomly chosen from inner neurons unioned with output neurons.
-    /// This is all the places where a gene can end, aka go to.
+    /// Returns a neuron id randomly chosen from input neurons unioned with the first
+    /// num_inner_neurons inner neurons. This is all the places where a gene can start from.
     /// Takes an optional "not" value, which, if supplied, will prevent this from returning
-    /// that value.
-    pub fn random_to_neuron(&self, not_id: Option<usize>) -> usize {
-        let num_neurons = self.inner_neurons.len() + self.output_neurons.len();
-        let idx = thread_rng().gen_range(0..num_neurons);
-
-        let id: usize;
-
-        if idx < self.inner_neurons.len() {
-            let ids = &self.inner_neurons.keys().map(|k| *k).collect();
-            id = get_id_not_id(ids, idx, not_id);
-        } else {
-            let ids = &self.output_neurons.keys().map(|k| *k).collect();
-            let index = idx - self.inner_neurons.len();
-            id = get_id_not_id(ids, index, not_id);
-        }
+    /// that value. There has to be some other neuron to return then.
+    pub fn random_from_neuron(
+        &self,
+        num_inner_neurons: usize,
+        not_id: Option<usize>,
+        rng: &mut impl Rng,
+    ) -> usize {
+        let ids: Vec<usize> = self
+            .from_neuron_ids(num_inner_neurons)
+            .filter(|id| Some(*id) != not_id)
+            .collect();
+
+        ids[rng.gen_range(0..ids.len())]
+    }
 
-        id
+    /// Returns a neuron id randomly chosen from the first num_inner_neurons inner neurons unioned
+    /// with output neurons. This is all the places where a gene can end, aka go to.
+    /// Takes an optional "not" value, which, if supplied, will prevent this from returning
+    /// that value. There has to be some other neuron to return then.
+    pub fn random_to_neuron(
+        &self,
+        num_inner_neurons: usize,
+        not_id: Option<usize>,
+        rng: &mut impl Rng,
+    ) -> usize {
+        let ids: Vec<usize> = self
+            .to_neuron_ids(num_inner_neurons)
+            .filter(|id| Some(*id) != not_id)
+            .collect();
+
+        ids[rng.gen_range(0..ids.len())]
     }
 
     pub fn neuron_type(&self, neuron_id: &usize) -> &NeuronType {
@@ -117,15 +163,115 @@ impl NeuralNetHelper {
     }
 }
 
-// Map of neuron id -> ..
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NeuralNet {
-    pub input_neurons: HashMap<usize, (InputNeuronType, InputNeuron)>,
-    pub inner_neurons: HashMap<usize, InnerNeuron>,
-    pub output_neurons: HashMap<usize, (OutputNeuronType, OutputNeuron)>,
+    /// The genome this net was spawned from, compiled. It holds the neurons' values too.
+    pub program: NeuralProgram,
+}
+
+impl NeuralNet {
+    /// Runs the compiled program, see LifeForm::run_neural_net.
+    pub fn run(&mut self, recurrent: bool) -> Vec<(OutputNeuronType, f32)> {
+        self.program.run(recurrent)
+    }
+
+    /// A neuron's value given the sum of what's coming into it. Inner and output neurons add
+    /// their bias and go through their own activation function; input neurons just go through
+    /// tanh.
+    pub fn activate(&self, neuron_id: usize, sum: f32) -> f32 {
+        match self.program.neuron(neuron_id) {
+            Some((bias, activation)) => activation.apply(sum + bias),
+            None => sum.tanh(),
+        }
+    }
+
+    /// The input neurons, in id order, with the values they were last given.
+    pub fn input_neurons(&self) -> impl Iterator<Item = (InputNeuronType, InputNeuron)> + '_ {
+        InputNeuronType::iter()
+            .zip(&self.program.inputs)
+            .enumerate()
+            .map(|(idx, (neuron_type, value))| {
+                let neuron = InputNeuron {
+                    id: FIRST_INPUT_NEURON + idx,
+                    value: *value,
+                };
+
+                (neuron_type, neuron)
+            })
+    }
+
+    /// The input neurons' values, to be filled in before running.
+    pub fn input_values_mut(&mut self) -> impl Iterator<Item = (InputNeuronType, &mut f32)> {
+        InputNeuronType::iter().zip(self.program.inputs.iter_mut())
+    }
+
+    /// The genome's inner neurons, in id order, with what they came out to on the last run.
+    pub fn inner_neurons(&self) -> impl Iterator<Item = InnerNeuron> + '_ {
+        self.program.inner.iter().enumerate().map(|(idx, value)| {
+            let id = FIRST_INNER_NEURON + idx;
+            let (bias, activation) = self.program.neuron(id).unwrap();
+
+            InnerNeuron {
+                id,
+                bias,
+                activation,
+                value: *value,
+            }
+        })
+    }
+
+    pub fn output_neurons(&self) -> impl Iterator<Item = (OutputNeuronType, OutputNeuron)> + '_ {
+        OutputNeuronType::iter()
+            .enumerate()
+            .map(|(idx, neuron_type)| {
+                let id = FIRST_OUTPUT_NEURON + idx;
+                let (bias, activation) = self.program.neuron(id).unwrap();
+                let neuron = OutputNeuron {
+                    id,
+                    bias,
+                    activation,
+                };
+
+                (neuron_type, neuron)
+            })
+    }
+}
+
+/// What a neuron does to its input before passing it on. Each inner and output neuron has its
+/// own, picked by its genome.
+#[derive(Debug, Default, Clone, Copy, PartialEq, EnumIter, Display, Serialize, Deserialize)]
+pub enum Activation {
+    #[default]
+    Tanh,
+    Sigmoid,
+    Relu,
+
+    /// 1 if the input is above 0, otherwise 0
+    Step,
+    Sin,
+    Identity,
+}
+
+impl Activation {
+    pub fn apply(&self, x: f32) -> f32 {
+        match self {
+            Activation::Tanh => x.tanh(),
+            Activation::Sigmoid => 1.0 / (1.0 + (-x).exp()),
+            Activation::Relu => x.max(0.0),
+            Activation::Step => {
+                if x > 0.0 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Activation::Sin => x.sin(),
+            Activation::Identity => x,
+        }
+    }
 }
 
-#[derive(Debug, EnumIter, Clone, Display)]
+#[derive(Debug, EnumIter, Clone, Display, Serialize, Deserialize)]
 pub enum InputNeuronType {
     DirectionToFood,
     DistanceToFood,
@@ -143,30 +289,51 @@ pub enum InputNeuronType {
     NeighborhoodDensity,
     Random,
     Oscillator,
+
+    /// 1 when the space in front of the lifeform is a wall or the edge of the world
+    WallAhead,
+
+    /// Where the closest carrion is. With no carrion around, it's as far away as can be.
+    DirectionToCarrion,
+    DistanceToCarrion,
+
+    /// What kind of danger the closest one is, see Hazard::menace
+    DangerKind,
 }
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct InputNeuron {
     pub id: usize,
     pub value: f32,
 }
 
-#[derive(Debug, EnumIter, Clone, Display)]
+#[derive(Debug, EnumIter, Clone, PartialEq, Display, Serialize, Deserialize)]
 pub enum OutputNeuronType {
     TurnLeft,
     TurnRight,
     MoveForward,
     Attack,
+
+    // New output neurons go at the end so the ids of the others don't shift
+    Mate,
 }
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct OutputNeuron {
     pub id: usize,
+    pub bias: f32,
+    pub activation: Activation,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct InnerNeuron {
     pub id: usize,
+    pub bias: f32,
+    pub activation: Activation,
+
+    /// What this neuron came out to the last time the net was run. In recurrent mode it's fed
+    /// back in on the next run, otherwise it's only kept for the UI.
+    pub value: f32,
 }
 
 #[derive(Debug, Clone)]
@@ -176,29 +343,54 @@ pub enum NeuronType {
     OutputNeuron,
 }
 
-/// The basic issue is that we want to request a random neuron id, but we sometimes want
-/// to make sure that it's different from a given one, which in this case is called not_id.
-/// This is just a helper to abstract some of the repeated logic in random_{from,to}_neuron.
-fn get_id_not_id(ids: &Vec<usize>, mut idx: usize, not_id: Option<usize>) -> usize {
-    let mut id = ids[idx];
-
-    if let Some(not_id) = not_id {
-        if not_id == id {
-            if idx > 0 {
-                idx -= 1;
-            } else {
-                idx += 1;
-            }
-        }
-    }
-
-    // On some occasions we may have a length one vector.
-    // In these cases, we'll just return the first id.
-    if idx >= ids.len() {
-        idx = 0;
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::thread_rng;
+
+    #[test]
+    fn neurons_use_their_bias_and_activation() {
+        let nnh = NeuralNetHelper::new();
+
+        let mut genome = Genome::new(
+            GenomeProps {
+                size: 5,
+                num_inner_neurons: 1,
+                mutation_rate: 0.1,
+                neural_net_helper: &nnh,
+            },
+            &mut Innovations::new(),
+            &mut thread_rng(),
+        );
+
+        genome.neurons.insert(
+            200,
+            NeuronGene {
+                bias: 1.0,
+                activation: Activation::Relu,
+            },
+        );
+        genome.neurons.insert(
+            300,
+            NeuronGene {
+                bias: -0.5,
+                activation: Activation::Step,
+            },
+        );
+
+        let neural_net = nnh.spawn(&genome);
+
+        assert_eq!(neural_net.activate(200, -0.5), 0.5);
+        assert_eq!(neural_net.activate(200, -2.0), 0.0);
+        assert_eq!(neural_net.activate(300, 0.6), 1.0);
+        assert_eq!(neural_net.activate(300, 0.4), 0.0);
+
+        // Untouched neurons, and inputs, are still tanh
+        assert_eq!(neural_net.activate(301, 0.3), 0.3_f32.tanh());
+        assert_eq!(neural_net.activate(100, 0.3), 0.3_f32.tanh());
+
+        assert_eq!(Activation::Sigmoid.apply(0.0), 0.5);
+        assert_eq!(Activation::Identity.apply(-3.0), -3.0);
+        assert_eq!(Activation::Sin.apply(0.0), 0.0);
     }
-
-    id = ids[idx];
-
-    id
 }
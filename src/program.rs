@@ -0,0 +1,234 @@
+use serde::{Deserialize, Serialize};
+
+use crate::*;
+
+/// One gene, with its neurons turned into positions in the program's buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Instruction {
+    pub from: u32,
+    pub to: u32,
+    pub weight: f32,
+}
+
+/// A genome compiled down to something that's quick to run every tic.
+///
+/// Every neuron gets a slot in one flat buffer: the input neurons first, then the genome's inner
+/// neurons, then the output neurons, each in id order. The genome's ordered genes become a list
+/// of instructions over those slots, so running the net is one pass over a Vec with no hashing
+/// and no allocating. It works out exactly the same numbers as LifeForm::run_neural_net_reference,
+/// which walks the genes the original way.
+///
+/// The neurons' values live here too, in plain Vecs, so spawning a net is just compiling this.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NeuralProgram {
+    num_inputs: usize,
+    num_inner: usize,
+    instructions: Vec<Instruction>,
+
+    /// Indexed by slot. Inputs are plain tanh with no bias.
+    biases: Vec<f32>,
+    activations: Vec<Activation>,
+
+    /// The inner neurons that some gene feeds into. Without recurrence, the others never get a
+    /// value.
+    fed_inner: Vec<bool>,
+
+    /// The output neurons that some gene feeds into, with their slots, in id order. Outputs
+    /// nothing feeds into don't get a value at all.
+    outputs: Vec<(OutputNeuronType, u32)>,
+
+    /// The input neurons' values, in id order. The world fills these in before every run.
+    pub inputs: Vec<f32>,
+
+    /// What each of the genome's inner neurons came out to the last time the program ran, in id
+    /// order. In recurrent mode they're fed back in on the next run, otherwise they're only kept
+    /// for the UI.
+    pub inner: Vec<f32>,
+
+    /// Where the running sums go while the program runs. Kept around between runs so running
+    /// doesn't allocate.
+    #[serde(skip)]
+    sums: Vec<f32>,
+}
+
+impl NeuralProgram {
+    pub fn compile(genome: &Genome, nnh: &NeuralNetHelper) -> Self {
+        let num_inputs = nnh.input_neurons.len();
+        let num_inner = genome.num_inner_neurons;
+        let num_slots = num_inputs + num_inner + nnh.output_neurons.len();
+
+        let mut program = Self {
+            num_inputs,
+            num_inner,
+            instructions: vec![],
+            biases: vec![0.0; num_slots],
+            activations: vec![Activation::Tanh; num_slots],
+            fed_inner: vec![],
+            outputs: vec![],
+            inputs: vec![0.0; num_inputs],
+            inner: vec![0.0; num_inner],
+            sums: vec![0.0; num_slots],
+        };
+
+        for id in nnh.biased_neuron_ids(num_inner) {
+            let neuron = genome.neuron(id);
+            let slot = program.slot(id).unwrap();
+            program.biases[slot] = neuron.bias;
+            program.activations[slot] = neuron.activation;
+        }
+
+        program.instructions = genome
+            .ordered_gene_indices
+            .iter()
+            .map(|idx| {
+                let gene = &genome.genes[*idx];
+
+                Instruction {
+                    from: program.slot(gene.from).unwrap() as u32,
+                    to: program.slot(gene.to).unwrap() as u32,
+                    weight: gene.weight,
+                }
+            })
+            .collect();
+
+        let mut fed = vec![false; num_slots];
+        for instruction in &program.instructions {
+            fed[instruction.to as usize] = true;
+        }
+
+        program.outputs = nnh
+            .output_neurons
+            .iter()
+            .map(|(id, (neuron_type, _))| (neuron_type.clone(), program.slot(*id).unwrap()))
+            .filter(|(_, slot)| fed[*slot])
+            .map(|(neuron_type, slot)| (neuron_type, slot as u32))
+            .collect();
+        program.fed_inner = fed[num_inputs..num_inputs + num_inner].to_vec();
+
+        program
+    }
+
+    /// Runs the program on the input values and leaves each inner neuron's new value in inner.
+    /// When recurrent is set the inner neurons start from the values already there, see
+    /// LifeForm::run_neural_net.
+    pub fn run(&mut self, recurrent: bool) -> Vec<(OutputNeuronType, f32)> {
+        let inner_start = self.num_inputs;
+        let inner_end = inner_start + self.num_inner;
+
+        // Programs that came out of a save file don't have their buffer yet
+        self.sums.resize(self.biases.len(), 0.0);
+        self.sums.fill(0.0);
+
+        self.sums[..inner_start].copy_from_slice(&self.inputs);
+
+        if recurrent {
+            self.sums[inner_start..inner_end].copy_from_slice(&self.inner);
+        }
+
+        for instruction in &self.instructions {
+            let from = instruction.from as usize;
+            let value = self.activations[from].apply(self.sums[from] + self.biases[from]);
+            self.sums[instruction.to as usize] += value * instruction.weight;
+        }
+
+        for idx in 0..self.num_inner {
+            self.inner[idx] = if recurrent || self.fed_inner[idx] {
+                self.activate(inner_start + idx)
+            } else {
+                0.0
+            };
+        }
+
+        self.outputs
+            .iter()
+            .map(|(neuron_type, slot)| (neuron_type.clone(), self.activate(*slot as usize)))
+            .collect()
+    }
+
+    /// The bias and activation of one of this program's inner or output neurons.
+    pub fn neuron(&self, neuron_id: usize) -> Option<(f32, Activation)> {
+        self.slot(neuron_id)
+            .filter(|slot| *slot >= self.num_inputs)
+            .map(|slot| (self.biases[slot], self.activations[slot]))
+    }
+
+    /// Where a neuron goes in the buffer, if this program has that neuron.
+    fn slot(&self, neuron_id: usize) -> Option<usize> {
+        let num_outputs = self.biases.len() - self.num_inputs - self.num_inner;
+
+        if (FIRST_INPUT_NEURON..FIRST_INPUT_NEURON + self.num_inputs).contains(&neuron_id) {
+            Some(neuron_id - FIRST_INPUT_NEURON)
+        } else if (FIRST_INNER_NEURON..FIRST_INNER_NEURON + self.num_inner).contains(&neuron_id) {
+            Some(self.num_inputs + neuron_id - FIRST_INNER_NEURON)
+        } else if (FIRST_OUTPUT_NEURON..FIRST_OUTPUT_NEURON + num_outputs).contains(&neuron_id) {
+            Some(self.num_inputs + self.num_inner + neuron_id - FIRST_OUTPUT_NEURON)
+        } else {
+            None
+        }
+    }
+
+    fn activate(&self, slot: usize) -> f32 {
+        self.activations[slot].apply(self.sums[slot] + self.biases[slot])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    #[test]
+    fn runs_the_same_as_the_reference() {
+        let nnh = NeuralNetHelper::new();
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        let mut innovations = Innovations::new();
+
+        let props = MutationProps::default();
+
+        for recurrent in [false, true] {
+            for idx in 0..50 {
+                let genome_props = GenomeProps {
+                    size: 5 + idx,
+                    num_inner_neurons: idx % 6,
+                    mutation_rate: 0.1,
+                    neural_net_helper: &nnh,
+                };
+
+                // Mutating gives the neurons some biases and activations besides tanh
+                let mut compiled =
+                    LifeForm::new(idx, (0, 0), genome_props, &mut innovations, &mut rng);
+                for _ in 0..10 {
+                    Evolver::mutate(
+                        &mut compiled.genome,
+                        &props,
+                        &nnh,
+                        &mut innovations,
+                        &mut rng,
+                    );
+                }
+                compiled.neural_net = nnh.spawn(&compiled.genome);
+
+                let mut reference = compiled.clone();
+
+                for _ in 0..5 {
+                    for value in compiled.neural_net.program.inputs.iter_mut() {
+                        *value = rng.gen_range(-1.0..=1.0);
+                    }
+                    reference.neural_net.program.inputs =
+                        compiled.neural_net.program.inputs.clone();
+
+                    assert_eq!(
+                        compiled.run_neural_net(recurrent),
+                        reference.run_neural_net_reference(&nnh, recurrent)
+                    );
+
+                    assert_eq!(
+                        compiled.neural_net.program.inner,
+                        reference.neural_net.program.inner
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::File,
+    io::{self, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::*;
+
+/// Bumped every time the shape of the save file changes, so that an old save is refused instead
+/// of being loaded into a world it no longer describes.
+pub const SNAPSHOT_VERSION: u32 = 23;
+
+/// Everything needed to resume a world exactly where it left off. Build one with
+/// world.snapshot() and turn it back into a world with World::from_snapshot().
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorldSnapshot {
+    pub version: u32,
+    pub props: WorldProps,
+    pub lifeforms: BTreeMap<usize, LifeForm>,
+    /// Where each food is and how grown it is. A list rather than a map, since JSON can't have
+    /// locations as keys.
+    pub food: Vec<((usize, usize), Food)>,
+    pub carrion: Vec<((usize, usize), f32)>,
+    pub hazards: Vec<Hazard>,
+    pub walls: BTreeSet<(usize, usize)>,
+    pub oscillator: f32,
+    pub rng: ChaCha8Rng,
+    pub tics: usize,
+    pub events: Vec<(EventType, String)>,
+    pub stats: Stats,
+    pub lineage: Lineage,
+    pub speciation: Speciation,
+    pub innovations: Innovations,
+    pub next_lifeform_id: usize,
+}
+
+impl WorldSnapshot {
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(&mut writer, self)?;
+        writer.flush()
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let reader = BufReader::new(File::open(path)?);
+        let snapshot: WorldSnapshot = serde_json::from_reader(reader)?;
+
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "save file is version {} but this build reads version {}",
+                    snapshot.version, SNAPSHOT_VERSION
+                ),
+            ));
+        }
+
+        Ok(snapshot)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn world_props() -> WorldProps {
+        WorldProps {
+            size: 30,
+            num_initial_lifeforms: 10,
+            genome_size: 10,
+            mutation_rate: 0.1,
+            food_density: 5,
+            num_inner_neurons: 2,
+            minimum_number_lifeforms: 5,
+            mutation: MutationProps {
+                max_genome_size: 20,
+                max_inner_neurons: 4,
+                ..MutationProps::default()
+            },
+            danger_delay: 10,
+            danger_damage: 0.5,
+            seed: 7,
+            stats_interval: 10,
+            ..WorldProps::default()
+        }
+    }
+
+    #[test]
+    fn round_trips_a_world_through_a_file() {
+        let props = world_props();
+        let nnh = NeuralNetHelper::new();
+        let mut world = World::new(props, &nnh);
+
+        for _ in 0..50 {
+            world.step();
+        }
+
+        let path =
+            std::env::temp_dir().join(format!("evolution-snapshot-{}.json", std::process::id()));
+        world.snapshot().save(&path).unwrap();
+        let loaded = WorldSnapshot::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut resumed = World::from_snapshot(loaded, &nnh);
+
+        assert_eq!(resumed.tics, world.tics);
+        assert_eq!(resumed.hazards, world.hazards);
+        assert_eq!(resumed.food, world.food);
+        assert_eq!(resumed.lifeforms.len(), world.lifeforms.len());
+
+        for (id, lf) in &world.lifeforms {
+            let other = &resumed.lifeforms[id];
+            assert_eq!(other.location, lf.location);
+            assert_eq!(other.lifespan, lf.lifespan);
+            assert_eq!(other.health, lf.health);
+            assert_eq!(
+                other.genome.ordered_gene_indices,
+                lf.genome.ordered_gene_indices
+            );
+        }
+
+        // The rng comes along too, so the resumed world carries on exactly as the original would
+        for _ in 0..50 {
+            world.step();
+            resumed.step();
+        }
+
+        assert_eq!(
+            serde_json::to_string(&resumed.snapshot()).unwrap(),
+            serde_json::to_string(&world.snapshot()).unwrap()
+        );
+    }
+
+    #[test]
+    fn refuses_other_versions() {
+        let nnh = NeuralNetHelper::new();
+        let world = World::new(world_props(), &nnh);
+
+        let mut snapshot = world.snapshot();
+        snapshot.version = SNAPSHOT_VERSION + 1;
+
+        let path =
+            std::env::temp_dir().join(format!("evolution-version-{}.json", std::process::id()));
+        snapshot.save(&path).unwrap();
+        let loaded = WorldSnapshot::load(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(loaded.is_err());
+    }
+}
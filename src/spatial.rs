@@ -0,0 +1,353 @@
+use std::ops::RangeInclusive;
+
+use crate::*;
+
+/// How many spaces across each cell of a SpatialIndex is.
+const CELL_SIZE: usize = 8;
+
+/// Buckets things by where they are in the world, so finding what's at, near or closest to a spot
+/// only has to look at the cells around it instead of everything in the world.
+///
+/// The world keeps one of these for its lifeforms (keyed by id) and one for its food (keyed by
+/// location). Whenever something moves, appears or goes away the index has to be told, see
+/// World::reindex.
+///
+/// In a bounded world anything off the edge goes in the nearest edge cell, so it's still found.
+/// In a toroidal one distances go the shortest way around, and searches wrap across the edges.
+#[derive(Debug, Clone)]
+pub struct SpatialIndex<T> {
+    topology: Topology,
+
+    /// How many locations there are along each side, 0 through size
+    around: usize,
+    cells_per_side: usize,
+    cells: Vec<Vec<(T, (usize, usize))>>,
+    len: usize,
+}
+
+impl<T: Copy + Ord> SpatialIndex<T> {
+    pub fn new(size: usize, topology: Topology) -> Self {
+        // Locations go from 0 up to and including size
+        let cells_per_side = size / CELL_SIZE + 1;
+
+        Self {
+            topology,
+            around: size + 1,
+            cells_per_side,
+            cells: vec![vec![]; cells_per_side * cells_per_side],
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.iter_mut().for_each(|cell| cell.clear());
+        self.len = 0;
+    }
+
+    pub fn insert(&mut self, item: T, location: (usize, usize)) {
+        let cell = self.cell_index(location);
+        self.cells[cell].push((item, location));
+        self.len += 1;
+    }
+
+    pub fn remove(&mut self, item: T, location: (usize, usize)) {
+        let cell_index = self.cell_index(location);
+        let cell = &mut self.cells[cell_index];
+
+        if let Some(idx) = cell.iter().position(|(i, _)| *i == item) {
+            cell.swap_remove(idx);
+            self.len -= 1;
+        }
+    }
+
+    pub fn relocate(&mut self, item: T, from: (usize, usize), to: (usize, usize)) {
+        if from != to {
+            self.remove(item, from);
+            self.insert(item, to);
+        }
+    }
+
+    /// Everything at exactly this location, smallest first.
+    pub fn at(&self, location: (usize, usize)) -> Vec<T> {
+        let mut items: Vec<T> = self.cells[self.cell_index(location)]
+            .iter()
+            .filter(|(_, loc)| *loc == location)
+            .map(|(item, _)| *item)
+            .collect();
+
+        items.sort_unstable();
+        items
+    }
+
+    /// Everything strictly closer than radius to the location.
+    pub fn within(
+        &self,
+        location: (usize, usize),
+        radius: f32,
+    ) -> impl Iterator<Item = (T, (usize, usize))> + '_ {
+        let reach = radius.ceil() as usize;
+        let xs = self.axis_cells(location.0, reach);
+        let ys = self.axis_cells(location.1, reach);
+        let radius_squared = radius * radius;
+
+        ys.0.chain(ys.1)
+            .flat_map(move |y| {
+                let xs = xs.clone();
+                xs.0.chain(xs.1).map(move |x| y * self.cells_per_side + x)
+            })
+            .flat_map(move |cell| self.cells[cell].iter().copied())
+            .filter(move |(_, loc)| (self.distance_squared(location, *loc) as f32) < radius_squared)
+    }
+
+    /// The closest thing to the location that isn't excluded, and where it is. When a few things
+    /// are just as close the smallest one wins, so the answer doesn't depend on the order things
+    /// went into the index.
+    ///
+    /// This looks at the location's own cell and then rings of cells further and further out,
+    /// and stops once the next ring is too far away to hold anything closer than what's been
+    /// found.
+    pub fn nearest(
+        &self,
+        location: (usize, usize),
+        exclude: impl Fn(&T) -> bool,
+    ) -> Option<(T, (usize, usize))> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let (cx, cy) = self.cell_coords(location);
+        let mut best: Option<(usize, T, (usize, usize))> = None;
+
+        // Going around a torus, every cell is within half way around
+        let last_ring = match self.topology {
+            Topology::Bounded => self.cells_per_side,
+            Topology::Toroidal => self.cells_per_side / 2,
+        };
+
+        for ring in 0..=last_ring {
+            if let Some((best_distance, _, _)) = best {
+                // Anything in this ring is at least this far away on one of the axes. Going
+                // around a torus one of the cells in between might be the short one at the end.
+                let full_cells_between = match self.topology {
+                    Topology::Bounded => ring - 1,
+                    Topology::Toroidal => ring.saturating_sub(2),
+                };
+                let closest_possible = full_cells_between * CELL_SIZE + 1;
+
+                if best_distance < closest_possible * closest_possible {
+                    break;
+                }
+            }
+
+            for cell in self.ring(cx, cy, ring) {
+                for (item, loc) in &self.cells[cell] {
+                    if exclude(item) {
+                        continue;
+                    }
+
+                    let candidate = (self.distance_squared(location, *loc), *item, *loc);
+
+                    if best.is_none_or(|b| (candidate.0, candidate.1) < (b.0, b.1)) {
+                        best = Some(candidate);
+                    }
+                }
+            }
+        }
+
+        best.map(|(_, item, loc)| (item, loc))
+    }
+
+    /// The indexes of the cells ring cells away from (cx, cy), going around the square. Only the
+    /// edge of the square is walked, so searching far out for something rare stays cheap.
+    fn ring(&self, cx: usize, cy: usize, ring: usize) -> impl Iterator<Item = usize> + '_ {
+        let (cx, cy, ring) = (cx as isize, cy as isize, ring as isize);
+        let side = self.cells_per_side as isize;
+
+        // The top and bottom rows are the same row for the middle cell
+        let rows = if ring == 0 { 1 } else { 2 };
+
+        let top_and_bottom = (cx - ring..=cx + ring)
+            .flat_map(move |x| [(x, cy - ring), (x, cy + ring)].into_iter().take(rows));
+        let left_and_right =
+            (cy - ring + 1..cy + ring).flat_map(move |y| [(cx - ring, y), (cx + ring, y)]);
+
+        let topology = self.topology;
+
+        top_and_bottom
+            .chain(left_and_right)
+            .filter_map(move |(x, y)| match topology {
+                Topology::Bounded => (x >= 0 && y >= 0 && x < side && y < side).then_some((x, y)),
+                // Small worlds can have rings that wrap onto themselves, which only means a
+                // few cells get looked at twice
+                Topology::Toroidal => Some((x.rem_euclid(side), y.rem_euclid(side))),
+            })
+            .map(move |(x, y)| (y * side + x) as usize)
+    }
+
+    /// The cells along one axis that hold everything within reach of center on that axis. Going
+    /// across the edge of a torus it's two runs of cells, otherwise the second is empty.
+    fn axis_cells(
+        &self,
+        center: usize,
+        reach: usize,
+    ) -> (RangeInclusive<usize>, RangeInclusive<usize>) {
+        let last = self.cells_per_side - 1;
+        #[allow(clippy::reversed_empty_ranges)]
+        let nothing = 1..=0;
+
+        match self.topology {
+            Topology::Bounded => {
+                let from = center.saturating_sub(reach) / CELL_SIZE;
+                let to = (center + reach) / CELL_SIZE;
+                (from.min(last)..=to.min(last), nothing)
+            }
+            Topology::Toroidal => {
+                if reach * 2 + 1 >= self.around {
+                    return (0..=last, nothing);
+                }
+
+                let around = self.around as isize;
+                let center = (center % self.around) as isize;
+                let (from, to) = (center - reach as isize, center + reach as isize);
+
+                if from >= 0 && to < around {
+                    return (from as usize / CELL_SIZE..=to as usize / CELL_SIZE, nothing);
+                }
+
+                // Crosses the edge, so it's the start of the axis and the end of it
+                let to_cell = to.rem_euclid(around) as usize / CELL_SIZE;
+                let from_cell = from.rem_euclid(around) as usize / CELL_SIZE;
+
+                if from_cell <= to_cell {
+                    (0..=last, nothing)
+                } else {
+                    (0..=to_cell, from_cell..=last)
+                }
+            }
+        }
+    }
+
+    fn cell_coords(&self, location: (usize, usize)) -> (usize, usize) {
+        match self.topology {
+            Topology::Bounded => {
+                let last = self.cells_per_side - 1;
+                (
+                    (location.0 / CELL_SIZE).min(last),
+                    (location.1 / CELL_SIZE).min(last),
+                )
+            }
+            Topology::Toroidal => (
+                (location.0 % self.around) / CELL_SIZE,
+                (location.1 % self.around) / CELL_SIZE,
+            ),
+        }
+    }
+
+    /// The square of the distance between two locations, going the shortest way around a torus.
+    fn distance_squared(&self, a: (usize, usize), b: (usize, usize)) -> usize {
+        let axis = |a: usize, b: usize| match self.topology {
+            Topology::Bounded => a.abs_diff(b),
+            Topology::Toroidal => {
+                let diff = (a % self.around).abs_diff(b % self.around);
+                diff.min(self.around - diff)
+            }
+        };
+
+        axis(a.0, b.0).pow(2) + axis(a.1, b.1).pow(2)
+    }
+
+    fn cell_index(&self, location: (usize, usize)) -> usize {
+        let (x, y) = self.cell_coords(location);
+        y * self.cells_per_side + x
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    #[test]
+    fn finds_the_same_things_as_looking_at_everything() {
+        for topology in [Topology::Bounded, Topology::Toroidal] {
+            // Small enough that searches wrap onto themselves, and big enough that they don't
+            for size in [10, 50] {
+                check_against_everything(topology, size);
+            }
+        }
+    }
+
+    fn check_against_everything(topology: Topology, size: usize) {
+        let mut rng = ChaCha8Rng::seed_from_u64(3);
+        let distance_squared = |a: (usize, usize), b: (usize, usize)| {
+            let (x, y) = topology.offset(size, &a, &b);
+            (x * x + y * y) as usize
+        };
+
+        let mut index = SpatialIndex::new(size, topology);
+        let mut items: Vec<(usize, (usize, usize))> = vec![];
+
+        for item in 0..200 {
+            let loc = (rng.gen_range(0..=size), rng.gen_range(0..=size));
+            index.insert(item, loc);
+            items.push((item, loc));
+        }
+
+        // Move some around and take some away
+        for (item, loc) in items.iter_mut().take(50) {
+            let to = (rng.gen_range(0..=size), rng.gen_range(0..=size));
+            index.relocate(*item, *loc, to);
+            *loc = to;
+        }
+        for (item, loc) in items.drain(150..) {
+            index.remove(item, loc);
+        }
+
+        assert_eq!(index.len(), items.len());
+
+        for _ in 0..100 {
+            let loc = (rng.gen_range(0..=size), rng.gen_range(0..=size));
+
+            let expected_nearest = items
+                .iter()
+                .filter(|(item, _)| item % 3 != 0)
+                .min_by_key(|(item, l)| (distance_squared(loc, *l), *item))
+                .copied();
+            assert_eq!(index.nearest(loc, |item| item % 3 == 0), expected_nearest);
+
+            for radius in [2.0, 4.0, 9.0] {
+                let mut expected_within: Vec<usize> = items
+                    .iter()
+                    .filter(|(_, l)| (distance_squared(loc, *l) as f32) < radius * radius)
+                    .map(|(item, _)| *item)
+                    .collect();
+                let mut within: Vec<usize> =
+                    index.within(loc, radius).map(|(item, _)| item).collect();
+                expected_within.sort();
+                within.sort();
+                assert_eq!(within, expected_within);
+            }
+        }
+
+        let loc = items[0].1;
+        index.insert(1000, loc);
+
+        let mut expected_at: Vec<usize> = items
+            .iter()
+            .filter(|(_, l)| *l == loc)
+            .map(|(item, _)| *item)
+            .chain([1000])
+            .collect();
+        expected_at.sort();
+        assert_eq!(index.at(loc), expected_at);
+    }
+}
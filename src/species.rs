@@ -0,0 +1,211 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::*;
+
+/// How lifeforms get grouped into species.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpeciesProps {
+    /// Every how many tics the lifeforms are sorted into species
+    pub interval: usize,
+
+    /// Two genomes closer than this (see Genome::distance) belong to the same species
+    pub threshold: f32,
+
+    /// How much genes that only one of the genomes has count towards the distance
+    pub disjoint_coefficient: f32,
+
+    /// How much the difference in weight between genes both genomes have counts towards the
+    /// distance
+    pub weight_coefficient: f32,
+}
+
+impl Default for SpeciesProps {
+    fn default() -> Self {
+        Self {
+            interval: 50,
+            threshold: 0.5,
+            disjoint_coefficient: 1.0,
+            weight_coefficient: 0.4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Species {
+    pub id: usize,
+
+    /// The genome new lifeforms are compared against to see if they belong to this species. It's
+    /// the genome of the oldest living member as of the last clustering.
+    pub representative: Genome,
+
+    /// The tic this species was first seen on
+    pub born: usize,
+
+    /// How many living lifeforms were in this species as of the last clustering
+    pub members: usize,
+}
+
+/// What changed in a clustering pass, so the world can turn it into events.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpeciesChange {
+    Born(usize),
+
+    /// The species id and the tic it was born on
+    Extinct(usize, usize),
+}
+
+/// Every species alive in a world, keyed by id. Like lifeform ids, species ids are never reused.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Speciation {
+    pub species: BTreeMap<usize, Species>,
+    next_species_id: usize,
+}
+
+impl Speciation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sorts every lifeform into a species, setting its species_id.
+    ///
+    /// A lifeform stays in the species it's already in if it's still close enough to that
+    /// species' representative. Otherwise it joins the oldest species it's close enough to, and
+    /// if there aren't any it starts a new one. Species nobody is left in go extinct.
+    pub fn cluster(
+        &mut self,
+        lifeforms: &mut BTreeMap<usize, LifeForm>,
+        props: &SpeciesProps,
+        tic: usize,
+    ) -> Vec<SpeciesChange> {
+        let mut changes = vec![];
+        let mut members: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+
+        for lf in lifeforms.values_mut() {
+            let is_close = |species: &Species| {
+                lf.genome.distance(&species.representative, props) < props.threshold
+            };
+
+            let current = lf
+                .species_id
+                .and_then(|id| self.species.get(&id))
+                .filter(|species| is_close(species))
+                .map(|species| species.id);
+
+            let species_id = match current.or_else(|| {
+                self.species
+                    .values()
+                    .find(|species| is_close(species))
+                    .map(|species| species.id)
+            }) {
+                Some(id) => id,
+                None => {
+                    let id = self.next_species_id;
+                    self.next_species_id += 1;
+
+                    self.species.insert(
+                        id,
+                        Species {
+                            id,
+                            representative: lf.genome.clone(),
+                            born: tic,
+                            members: 0,
+                        },
+                    );
+
+                    changes.push(SpeciesChange::Born(id));
+                    id
+                }
+            };
+
+            lf.species_id = Some(species_id);
+            members.entry(species_id).or_default().push(lf.id);
+        }
+
+        let extinct: Vec<usize> = self
+            .species
+            .keys()
+            .filter(|id| !members.contains_key(id))
+            .copied()
+            .collect();
+
+        for id in extinct {
+            let species = self.species.remove(&id).unwrap();
+            changes.push(SpeciesChange::Extinct(id, species.born));
+        }
+
+        for (id, ids) in members {
+            let species = self.species.get_mut(&id).unwrap();
+            species.members = ids.len();
+            species.representative = lifeforms[&ids[0]].genome.clone();
+        }
+
+        changes
+    }
+
+    /// The species with the most members, if there are any.
+    pub fn largest(&self) -> Option<&Species> {
+        self.species.values().max_by_key(|species| species.members)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::thread_rng;
+
+    #[test]
+    fn clusters_close_genomes_together() {
+        let nnh = NeuralNetHelper::new();
+        let props = SpeciesProps::default();
+        let mut rng = thread_rng();
+
+        let genome_props = GenomeProps {
+            size: 20,
+            num_inner_neurons: 3,
+            mutation_rate: 0.1,
+            neural_net_helper: &nnh,
+        };
+
+        let a = LifeForm::new(
+            0,
+            (0, 0),
+            genome_props.clone(),
+            &mut Innovations::new(),
+            &mut rng,
+        );
+        let mut a_clone = a.clone();
+        a_clone.id = 1;
+
+        // The same connections with every weight moved 2 away, which is too far to be related
+        let mut b = a.clone();
+        b.id = 2;
+        for gene in &mut b.genome.genes {
+            gene.weight += if gene.weight >= 0.0 { -2.0 } else { 2.0 };
+        }
+        assert!(b.genome.distance(&a.genome, &props) > props.threshold);
+
+        let mut lifeforms: BTreeMap<usize, LifeForm> =
+            [a, a_clone, b].into_iter().map(|lf| (lf.id, lf)).collect();
+
+        let mut speciation = Speciation::new();
+        let changes = speciation.cluster(&mut lifeforms, &props, 0);
+
+        assert_eq!(
+            changes,
+            vec![SpeciesChange::Born(0), SpeciesChange::Born(1)]
+        );
+        assert_eq!(lifeforms[&0].species_id, Some(0));
+        assert_eq!(lifeforms[&1].species_id, Some(0));
+        assert_eq!(lifeforms[&2].species_id, Some(1));
+        assert_eq!(speciation.largest().unwrap().id, 0);
+
+        lifeforms.remove(&2);
+        let changes = speciation.cluster(&mut lifeforms, &props, 50);
+
+        assert_eq!(changes, vec![SpeciesChange::Extinct(1, 0)]);
+        assert_eq!(speciation.species[&0].members, 2);
+    }
+}
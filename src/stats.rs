@@ -0,0 +1,369 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use clap::ValueEnum;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::*;
+
+/// A picture of the population at one point in time. Counts like births and deaths are totals
+/// since the previous record.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StatsRecord {
+    pub tic: usize,
+    pub population: usize,
+    pub mean_lifespan: f32,
+    pub max_lifespan: usize,
+    pub mean_health: f32,
+    pub mean_hunger: f32,
+    pub births: usize,
+    pub deaths: usize,
+    pub attacks: usize,
+    pub food: usize,
+    pub genome_diversity: f32,
+
+    /// How many of the births came from mating
+    pub matings: usize,
+
+    /// How many mutations were made to newborns' genomes
+    pub mutations: usize,
+
+    /// The mean of the mutation rates the living lifeforms' genomes carry
+    pub mean_mutation_rate: f32,
+
+    /// How many species the living lifeforms are split into
+    pub species: usize,
+}
+
+const CSV_HEADER: &str = "tic,population,mean_lifespan,max_lifespan,mean_health,mean_hunger,births,deaths,attacks,food,genome_diversity,matings,mutations,mean_mutation_rate,species";
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum StatsFormat {
+    Csv,
+    Jsonl,
+}
+
+/// Time series of population statistics for a world. The world tallies births, deaths and
+/// attacks as they happen and calls record() every stats_interval tics to close out a row.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Stats {
+    pub history: Vec<StatsRecord>,
+
+    births: usize,
+    deaths: usize,
+    attacks: usize,
+    matings: usize,
+    mutations: usize,
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_birth(&mut self) {
+        self.births += 1;
+    }
+
+    pub fn record_death(&mut self) {
+        self.deaths += 1;
+    }
+
+    pub fn record_attack(&mut self) {
+        self.attacks += 1;
+    }
+
+    /// Called alongside record_birth for children of mating
+    pub fn record_mating(&mut self) {
+        self.matings += 1;
+    }
+
+    pub fn record_mutations(&mut self, count: usize) {
+        self.mutations += count;
+    }
+
+    /// Take a row for the time series out of the current population and reset the tallies. The
+    /// rng is for sampling the genome diversity of a big population.
+    pub fn record(
+        &mut self,
+        tic: usize,
+        lifeforms: &BTreeMap<usize, LifeForm>,
+        food: usize,
+        rng: &mut impl Rng,
+    ) {
+        let population = lifeforms.len();
+        let mean = |f: &dyn Fn(&LifeForm) -> f32| {
+            if population == 0 {
+                0.0
+            } else {
+                lifeforms.values().map(f).sum::<f32>() / population as f32
+            }
+        };
+
+        self.history.push(StatsRecord {
+            tic,
+            population,
+            mean_lifespan: mean(&|lf| lf.lifespan as f32),
+            max_lifespan: lifeforms.values().map(|lf| lf.lifespan).max().unwrap_or(0),
+            mean_health: mean(&|lf| lf.health),
+            mean_hunger: mean(&|lf| lf.hunger),
+            births: self.births,
+            deaths: self.deaths,
+            attacks: self.attacks,
+            food,
+            genome_diversity: genome_diversity(lifeforms, rng),
+            matings: self.matings,
+            mutations: self.mutations,
+            mean_mutation_rate: mean(&|lf| lf.genome.mutation_rate),
+            species: lifeforms
+                .values()
+                .filter_map(|lf| lf.species_id)
+                .collect::<BTreeSet<usize>>()
+                .len(),
+        });
+
+        self.births = 0;
+        self.deaths = 0;
+        self.attacks = 0;
+        self.matings = 0;
+        self.mutations = 0;
+    }
+
+    pub fn write_csv(&self, writer: &mut impl Write) -> io::Result<()> {
+        writeln!(writer, "{}", CSV_HEADER)?;
+
+        for r in &self.history {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+                r.tic,
+                r.population,
+                r.mean_lifespan,
+                r.max_lifespan,
+                r.mean_health,
+                r.mean_hunger,
+                r.births,
+                r.deaths,
+                r.attacks,
+                r.food,
+                r.genome_diversity,
+                r.matings,
+                r.mutations,
+                r.mean_mutation_rate,
+                r.species
+            )?;
+        }
+
+        Ok(())
+    }
+
+    pub fn write_json_lines(&self, writer: &mut impl Write) -> io::Result<()> {
+        for record in &self.history {
+            serde_json::to_writer(&mut *writer, record)?;
+            writeln!(writer)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn write_to_file(&self, path: &Path, format: StatsFormat) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        match format {
+            StatsFormat::Csv => self.write_csv(&mut writer)?,
+            StatsFormat::Jsonl => self.write_json_lines(&mut writer)?,
+        }
+
+        writer.flush()
+    }
+}
+
+/// The most pairs of lifeforms genome_diversity compares. Comparing every pair gets slow fast as
+/// the population grows, so past this many it compares a random sample of pairs instead.
+const DIVERSITY_PAIRS: usize = 1000;
+
+/// The mean, over every pair of lifeforms, of the fraction of their genes that differ. 0 means
+/// the whole population shares one genome, 1 means no two lifeforms share a single gene. In a
+/// population with more than DIVERSITY_PAIRS pairs, it's the mean over that many random pairs.
+pub fn genome_diversity(lifeforms: &BTreeMap<usize, LifeForm>, rng: &mut impl Rng) -> f32 {
+    let genomes: Vec<&Genome> = lifeforms.values().map(|lf| &lf.genome).collect();
+    let count = genomes.len();
+
+    if count < 2 {
+        return 0.0;
+    }
+
+    let mut total = 0.0;
+    let mut pairs = 0;
+
+    if count * (count - 1) / 2 <= DIVERSITY_PAIRS {
+        for (i, a) in genomes.iter().enumerate() {
+            for b in &genomes[i + 1..] {
+                total += gene_difference(a, b);
+                pairs += 1;
+            }
+        }
+    } else {
+        for _ in 0..DIVERSITY_PAIRS {
+            // Any two different lifeforms
+            let i = rng.gen_range(0..count);
+            let j = (i + rng.gen_range(1..count)) % count;
+
+            total += gene_difference(genomes[i], genomes[j]);
+            pairs += 1;
+        }
+    }
+
+    total / pairs as f32
+}
+
+/// Compares genes position by position. Genes past the end of the shorter genome count as
+/// different.
+fn gene_difference(a: &Genome, b: &Genome) -> f32 {
+    let longest = a.genes.len().max(b.genes.len());
+
+    if longest == 0 {
+        return 0.0;
+    }
+
+    let shared = a.genes.len().min(b.genes.len());
+
+    let differing = a
+        .genes
+        .iter()
+        .zip(&b.genes)
+        .filter(|(x, y)| {
+            x.from != y.from || x.to != y.to || x.weight != y.weight || x.enabled != y.enabled
+        })
+        .count();
+
+    (differing + longest - shared) as f32 / longest as f32
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::{thread_rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    fn lifeforms(nnh: &NeuralNetHelper, count: usize) -> BTreeMap<usize, LifeForm> {
+        (0..count)
+            .map(|id| {
+                let genome_props = GenomeProps {
+                    size: 10,
+                    num_inner_neurons: 2,
+                    mutation_rate: 0.1,
+                    neural_net_helper: nnh,
+                };
+
+                let lf = LifeForm::new(
+                    id,
+                    (0, 0),
+                    genome_props,
+                    &mut Innovations::new(),
+                    &mut thread_rng(),
+                );
+
+                (id, lf)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn records_and_resets_tallies() {
+        let nnh = NeuralNetHelper::new();
+        let lfs = lifeforms(&nnh, 4);
+
+        let mut stats = Stats::new();
+        stats.record_birth();
+        stats.record_birth();
+        stats.record_death();
+        stats.record_attack();
+        stats.record(10, &lfs, 3, &mut thread_rng());
+        stats.record(20, &lfs, 3, &mut thread_rng());
+
+        assert_eq!(stats.history.len(), 2);
+        assert_eq!(stats.history[0].population, 4);
+        assert_eq!(stats.history[0].births, 2);
+        assert_eq!(stats.history[0].deaths, 1);
+        assert_eq!(stats.history[0].attacks, 1);
+        assert_eq!(stats.history[0].mean_health, 1.0);
+        assert_eq!(stats.history[1].births, 0);
+        assert_eq!(stats.history[1].tic, 20);
+    }
+
+    #[test]
+    fn writes_csv_and_json_lines() {
+        let nnh = NeuralNetHelper::new();
+        let lfs = lifeforms(&nnh, 3);
+
+        let mut stats = Stats::new();
+        stats.record(1, &lfs, 0, &mut thread_rng());
+        stats.record(2, &lfs, 5, &mut thread_rng());
+
+        let mut csv = vec![];
+        stats.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], CSV_HEADER);
+        assert!(lines[2].starts_with("2,3,"));
+
+        let mut jsonl = vec![];
+        stats.write_json_lines(&mut jsonl).unwrap();
+        let parsed: Vec<StatsRecord> = String::from_utf8(jsonl)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(parsed, stats.history);
+    }
+
+    #[test]
+    fn identical_genomes_have_no_diversity() {
+        let nnh = NeuralNetHelper::new();
+        let mut lfs = lifeforms(&nnh, 3);
+        let genome = lfs[&0].genome.clone();
+
+        for lf in lfs.values_mut() {
+            lf.genome = genome.clone();
+        }
+
+        assert_eq!(genome_diversity(&lfs, &mut thread_rng()), 0.0);
+
+        lfs.get_mut(&1).unwrap().genome.genes[0].weight += 1.0;
+        assert!(genome_diversity(&lfs, &mut thread_rng()) > 0.0);
+    }
+
+    #[test]
+    fn samples_the_diversity_of_a_big_population() {
+        let nnh = NeuralNetHelper::new();
+        let mut rng = ChaCha8Rng::seed_from_u64(3);
+
+        // Two camps of 50 that don't share a single gene, so 2500 of the 4950 pairs differ
+        // completely and the rest not at all
+        let mut lfs = lifeforms(&nnh, 100);
+        let one = lfs[&0].genome.clone();
+        let mut other = one.clone();
+
+        for gene in &mut other.genes {
+            gene.weight += 10.0;
+        }
+
+        for lf in lfs.values_mut() {
+            lf.genome = if lf.id < 50 {
+                one.clone()
+            } else {
+                other.clone()
+            };
+        }
+
+        let diversity = genome_diversity(&lfs, &mut rng);
+        assert!((diversity - 2500.0 / 4950.0).abs() < 0.05);
+    }
+}
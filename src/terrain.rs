@@ -0,0 +1,197 @@
+use std::{collections::BTreeSet, fs, path::Path, path::PathBuf};
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::*;
+
+/// Where the walls in a world come from. Walls are spaces nothing can move into: lifeforms bump
+/// into them, the danger has to go around them, and food never grows on them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WallProps {
+    /// A text file to read the walls from, see load_walls. When this is given the walls aren't
+    /// generated.
+    pub map: Option<PathBuf>,
+
+    /// Roughly what fraction of the world is covered in generated walls. 0 means no walls.
+    pub density: f32,
+
+    /// The longest a generated wall gets. Some come out a single space long, like rocks.
+    pub max_length: usize,
+}
+
+impl Default for WallProps {
+    fn default() -> Self {
+        Self {
+            map: None,
+            density: 0.0,
+            max_length: 8,
+        }
+    }
+}
+
+/// Reads walls from a text file that looks like the world: every `#` is a wall and anything else
+/// is open. The first line is the top of the world (the north edge), so the map reads the same
+/// way the world is drawn. Anything past the edge of the world is left out.
+pub fn load_walls(path: &Path, size: usize) -> Result<BTreeSet<(usize, usize)>, ConfigError> {
+    let contents = fs::read_to_string(path)?;
+    let mut walls = BTreeSet::new();
+
+    for (row, line) in contents.lines().enumerate().take(size + 1) {
+        for (x, c) in line.chars().enumerate().take(size + 1) {
+            if c == '#' {
+                walls.insert((x, size - row));
+            }
+        }
+    }
+
+    Ok(walls)
+}
+
+/// Scatters straight walls of random lengths over the world until about density of it is
+/// covered. However dense, at least one space below size on both axes is left open, since that's
+/// where new food and lifeforms go.
+pub fn generate_walls(
+    topology: Topology,
+    size: usize,
+    props: &WallProps,
+    rng: &mut impl Rng,
+) -> BTreeSet<(usize, usize)> {
+    let mut walls = BTreeSet::new();
+    let all = (size + 1) * (size + 1);
+    let target = ((props.density * all as f32) as usize).min(all - 1);
+
+    // How many of the spaces new things can go in are still open
+    let mut open_below_size = size * size;
+
+    let mut build = |walls: &mut BTreeSet<(usize, usize)>, loc: (usize, usize)| {
+        let below_size = loc.0 < size && loc.1 < size;
+
+        if walls.contains(&loc) || (below_size && open_below_size == 1) {
+            return false;
+        }
+
+        walls.insert(loc);
+        if below_size {
+            open_below_size -= 1;
+        }
+        true
+    };
+
+    while walls.len() < target {
+        let mut loc = (rng.gen_range(0..=size), rng.gen_range(0..=size));
+        let modifier = [(0, 1), (1, 0), (0, -1), (-1, 0)][rng.gen_range(0..4)];
+        let length = rng.gen_range(1..=props.max_length.max(1));
+
+        if !build(&mut walls, loc) {
+            continue;
+        }
+
+        for _ in 1..length {
+            match step_to(topology, size, &walls, &loc, &modifier) {
+                Some(next) if walls.len() < target && build(&mut walls, next) => loc = next,
+                _ => break,
+            }
+        }
+    }
+
+    walls
+}
+
+/// Where taking one step by modifier from loc ends up, see Topology::update_location. None if
+/// that's a wall.
+pub fn step_to(
+    topology: Topology,
+    size: usize,
+    walls: &BTreeSet<(usize, usize)>,
+    loc: &(usize, usize),
+    modifier: &(i8, i8),
+) -> Option<(usize, usize)> {
+    let mut to = *loc;
+    topology.update_location(size, &mut to, modifier);
+
+    if walls.contains(&to) {
+        None
+    } else {
+        Some(to)
+    }
+}
+
+/// Whether a step by modifier from loc can't go anywhere, because of a wall or the edge of a
+/// bounded world.
+pub fn is_blocked(
+    topology: Topology,
+    size: usize,
+    walls: &BTreeSet<(usize, usize)>,
+    loc: &(usize, usize),
+    modifier: &(i8, i8),
+) -> bool {
+    step_to(topology, size, walls, loc, modifier).is_none_or(|to| to == *loc)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    #[test]
+    fn reads_a_map_the_way_it_looks() {
+        let path = std::env::temp_dir().join(format!("evolution-map-{}", std::process::id()));
+        fs::write(&path, "#..\n.#.\n..#\n").unwrap();
+
+        let walls = load_walls(&path, 2).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        // Top left to bottom right
+        assert_eq!(walls, BTreeSet::from([(0, 2), (1, 1), (2, 0)]));
+    }
+
+    #[test]
+    fn generates_about_as_many_walls_as_asked() {
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let props = WallProps {
+            density: 0.2,
+            ..WallProps::default()
+        };
+
+        let walls = generate_walls(Topology::Bounded, 49, &props, &mut rng);
+
+        assert_eq!(walls.len(), 500);
+        assert!(walls.iter().all(|(x, y)| *x <= 49 && *y <= 49));
+        assert!(is_blocked(
+            Topology::Bounded,
+            49,
+            &[].into(),
+            &(0, 0),
+            &(-1, 0)
+        ));
+        assert!(!is_blocked(
+            Topology::Toroidal,
+            49,
+            &[].into(),
+            &(0, 0),
+            &(-1, 0)
+        ));
+    }
+
+    #[test]
+    fn always_leaves_somewhere_open() {
+        let props = WallProps {
+            density: 0.99,
+            ..WallProps::default()
+        };
+
+        for seed in 0..20 {
+            let mut rng = ChaCha8Rng::seed_from_u64(seed);
+            let walls = generate_walls(Topology::Bounded, 2, &props, &mut rng);
+
+            let open = (0..2)
+                .flat_map(|x| (0..2).map(move |y| (x, y)))
+                .filter(|loc| !walls.contains(loc))
+                .count();
+            assert_eq!(open, 1);
+        }
+    }
+}
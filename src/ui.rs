@@ -1,25 +1,61 @@
-use std::collections::{HashMap, BTreeSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
 use tui::{
     backend::Backend,
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
+    symbols,
     text::{Span, Spans},
     widgets::{
         canvas::{Canvas, Line},
-        Block, Borders, List, ListItem, Paragraph,
+        Axis, Block, Borders, Chart, Dataset, GraphType, List, ListItem, Paragraph,
     },
     Frame,
 };
 
 use crate::*;
 
+/// How many of the most recent stats records the chart shows
+const CHART_HISTORY_LENGTH: usize = 100;
+
+/// Which time series the chart panel is plotting
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChartSeries {
+    Population,
+    AverageAge,
+    BirthsAndDeaths,
+    Food,
+}
+
+impl ChartSeries {
+    /// The series after this one, wrapping around at the end
+    pub fn next(self) -> Self {
+        match self {
+            ChartSeries::Population => ChartSeries::AverageAge,
+            ChartSeries::AverageAge => ChartSeries::BirthsAndDeaths,
+            ChartSeries::BirthsAndDeaths => ChartSeries::Food,
+            ChartSeries::Food => ChartSeries::Population,
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            ChartSeries::Population => "Population",
+            ChartSeries::AverageAge => "Average Age",
+            ChartSeries::BirthsAndDeaths => "Births / Deaths",
+            ChartSeries::Food => "Food",
+        }
+    }
+}
+
 pub fn ui<B>(
     f: &mut Frame<B>,
     size: usize,
     world: &World,
     selected_lf: Option<&LifeForm>,
     tick_rate: u64,
+    status: Option<&str>,
+    chart_series: ChartSeries,
 ) where
     B: Backend,
 {
@@ -29,8 +65,8 @@ pub fn ui<B>(
         .constraints([Constraint::Length(size as u16), Constraint::Min(20)].as_ref())
         .split(f.size());
 
-    draw_main(f, size, selected_lf, tick_rate, world, chunks[0]);
-    draw_controls(f, chunks[1]);
+    draw_main(f, size, selected_lf, tick_rate, chart_series, world, chunks[0]);
+    draw_controls(f, status, chunks[1]);
 }
 
 fn draw_main<B>(
@@ -38,6 +74,7 @@ fn draw_main<B>(
     size: usize,
     selected_lf: Option<&LifeForm>,
     tick_rate: u64,
+    chart_series: ChartSeries,
     world: &World,
     area: Rect,
 ) where
@@ -50,18 +87,22 @@ fn draw_main<B>(
         .split(area);
 
     draw_world(f, size, selected_lf, world, chunks[0]);
-    draw_right(f, selected_lf, tick_rate, world, chunks[1]);
+    draw_right(f, selected_lf, tick_rate, chart_series, world, chunks[1]);
 }
 
-fn draw_controls<B>(f: &mut Frame<B>, area: Rect)
+fn draw_controls<B>(f: &mut Frame<B>, status: Option<&str>, area: Rect)
 where
     B: Backend,
 {
     let block = Block::default().title("Controls").borders(Borders::ALL);
-    let text = vec![Spans::from(
-        "q = quit | p = pause | d = pause drawing | Up/Down = Select LifeForm | Left/Right = change tick rate",
+    let mut text = vec![Spans::from(
+        "q = quit | p = pause | d = pause drawing | s = save world | c = next chart | Up/Down = Select LifeForm | Left/Right = change tick rate",
     )];
 
+    if let Some(status) = status {
+        text.push(Spans::from(Span::styled(status, Style::default().fg(Color::Yellow))));
+    }
+
     let paragraph = Paragraph::new(text).block(block);
 
     f.render_widget(paragraph, area);
@@ -81,11 +122,30 @@ fn draw_world<B>(
         .x_bounds([0.0, size as f64])
         .y_bounds([0.0, size as f64])
         .paint(|ctx| {
-            for food in &world.food {
+            for wall in &world.walls {
+                ctx.print(
+                    wall.0 as f64,
+                    wall.1 as f64,
+                    Span::styled("█", Style::default().fg(Color::DarkGray)),
+                );
+            }
+
+            // Food that's still growing is drawn small
+            for (loc, food) in &world.food {
+                let glyph = if food.energy < 1.0 { "f" } else { "F" };
+
+                ctx.print(
+                    loc.0 as f64,
+                    loc.1 as f64,
+                    Span::styled(glyph, Style::default().fg(Color::Green)),
+                );
+            }
+
+            for loc in world.carrion.keys() {
                 ctx.print(
-                    food.0 as f64,
-                    food.1 as f64,
-                    Span::styled("F", Style::default().fg(Color::Green)),
+                    loc.0 as f64,
+                    loc.1 as f64,
+                    Span::styled("%", Style::default().fg(Color::Gray)),
                 );
             }
 
@@ -128,21 +188,7 @@ fn draw_world<B>(
                     _ => "#",
                 };
 
-                let color = match lf.health {
-                    _ if lf.health >= 0.9 => Color::LightGreen,
-                    _ if lf.health >= 0.8 => Color::Green,
-                    _ if lf.health >= 0.7 => Color::LightBlue,
-                    _ if lf.health >= 0.6 => Color::Blue,
-                    _ if lf.health >= 0.5 => Color::Magenta,
-                    _ if lf.health >= 0.4 => Color::LightMagenta,
-                    _ if lf.health >= 0.3 => Color::Yellow,
-                    _ if lf.health >= 0.2 => Color::LightYellow,
-                    _ if lf.health >= 0.1 => Color::LightRed,
-                    _ if lf.health < 0.1 => Color::Red,
-                    _ => Color::White,
-                };
-
-                let mut style = Style::default().fg(color);
+                let mut style = Style::default().fg(species_color(lf.species_id));
 
                 if let Some(selected_lf) = selected_lf {
                     if lf.id == selected_lf.id {
@@ -158,25 +204,51 @@ fn draw_world<B>(
 
             }
 
-            ctx.print(
-                world.danger.0 as f64,
-                world.danger.1 as f64,
-                Span::styled(
-                    "☢",
-                    Style::default()
-                        .fg(Color::Red)
-                        .add_modifier(Modifier::BOLD),
-                ),
-            );
+            for hazard in &world.hazards {
+                ctx.print(
+                    hazard.location.0 as f64,
+                    hazard.location.1 as f64,
+                    Span::styled(
+                        hazard.glyph(),
+                        Style::default()
+                            .fg(Color::Red)
+                            .add_modifier(Modifier::BOLD),
+                    ),
+                );
+            }
         });
 
     f.render_widget(world_canvas, area);
 }
 
+/// Lifeforms are coloured by species. There are only so many colours a terminal can be relied on
+/// to have, so once there are more species than colours some will share. White is left out since
+/// that's for the selected lifeform, and so are the food's green and the dangers' red.
+fn species_color(species_id: Option<usize>) -> Color {
+    const SPECIES_COLORS: [Color; 10] = [
+        Color::Yellow,
+        Color::Cyan,
+        Color::Magenta,
+        Color::LightBlue,
+        Color::LightRed,
+        Color::LightYellow,
+        Color::LightCyan,
+        Color::LightMagenta,
+        Color::Blue,
+        Color::LightGreen,
+    ];
+
+    match species_id {
+        Some(id) => SPECIES_COLORS[id % SPECIES_COLORS.len()],
+        None => Color::DarkGray,
+    }
+}
+
 fn draw_right<B>(
     f: &mut Frame<B>,
     selected_lf: Option<&LifeForm>,
     tick_rate: u64,
+    chart_series: ChartSeries,
     world: &World,
     area: Rect,
 ) where
@@ -188,7 +260,7 @@ fn draw_right<B>(
         .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
         .split(area);
 
-    draw_top_right(f, selected_lf, tick_rate, world, chunks[0]);
+    draw_top_right(f, selected_lf, tick_rate, chart_series, world, chunks[0]);
     draw_single_lf_information(f, selected_lf, chunks[1]);
 }
 
@@ -224,19 +296,39 @@ where
     B: Backend,
 {
 
-    if let None = selected_lf {
+    if selected_lf.is_none() {
         return;
     }
 
     let lf = selected_lf.unwrap();
 
-    let mut items: Vec<ListItem> = vec![];
-
-    items.push(ListItem::new("Health:"));
-    items.push(ListItem::new(lf.health.to_string()));
-
-    items.push(ListItem::new("Hunger:"));
-    items.push(ListItem::new(lf.hunger.to_string()));
+    let items: Vec<ListItem> = vec![
+        ListItem::new("Health:"),
+        ListItem::new(lf.health.to_string()),
+        ListItem::new("Hunger:"),
+        ListItem::new(lf.hunger.to_string()),
+        ListItem::new("Genes:"),
+        ListItem::new(lf.genome.genes.len().to_string()),
+        ListItem::new("Inner Neurons:"),
+        ListItem::new(lf.genome.num_inner_neurons.to_string()),
+        ListItem::new("Mutation Rate:"),
+        ListItem::new(format!("{:.4}", lf.genome.mutation_rate)),
+        ListItem::new("Generation:"),
+        ListItem::new(lf.generation.to_string()),
+        ListItem::new("Species:"),
+        ListItem::new(Span::styled(
+            lf.species_id
+                .map(|id| id.to_string())
+                .unwrap_or_else(|| String::from("none")),
+            Style::default().fg(species_color(lf.species_id)),
+        )),
+        ListItem::new("Parents:"),
+        ListItem::new(match (lf.parent_id, lf.other_parent_id) {
+            (Some(a), Some(b)) => format!("{} & {}", a, b),
+            (Some(a), None) => a.to_string(),
+            _ => String::from("none"),
+        }),
+    ];
 
     let list = List::new(items).block(
         Block::default()
@@ -284,7 +376,7 @@ where
                     }
                 }
 
-                let span = Span::from(Span::styled(txt, style));
+                let span = Span::styled(txt, style);
 
                 ctx.print(column, row, span);
             }
@@ -297,13 +389,13 @@ fn draw_lf_input_neuron_values<B>(f: &mut Frame<B>, selected_lf: Option<&LifeFor
 where
     B: Backend,
 {
-    if let None = selected_lf {
+    if selected_lf.is_none() {
         return;
     }
 
     let mut items: Vec<ListItem> = vec![];
 
-    for (idx, (neuron_type, neuron)) in selected_lf.unwrap().neural_net.input_neurons.values().enumerate() {
+    for (idx, (neuron_type, neuron)) in selected_lf.unwrap().neural_net.input_neurons().enumerate() {
         items.push(ListItem::new(format!(
             "{} - {:?}: {:?}",
             idx, neuron_type, neuron.value
@@ -326,13 +418,13 @@ fn draw_lf_output_neuron_values<B>(f: &mut Frame<B>, selected_lf: Option<&LifeFo
 where
     B: Backend,
 {
-    if let None = selected_lf {
+    if selected_lf.is_none() {
         return;
     }
 
     let mut values: BTreeSet<String> = BTreeSet::new();
 
-    if let None = selected_lf.unwrap().most_recent_output_neuron_values {
+    if selected_lf.unwrap().most_recent_output_neuron_values.is_none() {
         return;
     } else {
         let lf = selected_lf.unwrap();
@@ -348,9 +440,19 @@ where
         items.push(ListItem::new(st));
     }
 
+    for neuron in selected_lf.unwrap().neural_net.inner_neurons() {
+        items.push(
+            ListItem::new(format!(
+                "Inner {} ({}): {:.4}",
+                neuron.id, neuron.activation, neuron.value
+            ))
+            .style(Style::default().fg(Color::Gray)),
+        );
+    }
+
     let list = List::new(items).block(
         Block::default()
-            .title("Output Neuron Values")
+            .title("Neuron Values")
             .borders(Borders::ALL),
     );
 
@@ -361,7 +463,7 @@ fn draw_lf_neural_net<B>(f: &mut Frame<B>, selected_lf: Option<&LifeForm>, area:
 where
     B: Backend,
 {
-    if let None = selected_lf {
+    if selected_lf.is_none() {
         return;
     }
 
@@ -393,7 +495,7 @@ where
             }
 
             for (name, loc) in neuron_locs.values() {
-                ctx.print(loc.0, loc.1, Span::from(Span::styled(String::from(name), Style::default().fg(Color::White))));
+                ctx.print(loc.0, loc.1, Span::styled(String::from(name), Style::default().fg(Color::White)));
             }
         });
 
@@ -404,6 +506,7 @@ fn draw_top_right<B>(
     f: &mut Frame<B>,
     selected_lf: Option<&LifeForm>,
     tick_rate: u64,
+    chart_series: ChartSeries,
     world: &World,
     area: Rect,
 ) where
@@ -412,11 +515,91 @@ fn draw_top_right<B>(
     let chunks = Layout::default()
         .direction(Direction::Horizontal)
         .margin(0)
-        .constraints([Constraint::Percentage(35), Constraint::Percentage(65)].as_ref())
+        .constraints(
+            [
+                Constraint::Percentage(30),
+                Constraint::Percentage(30),
+                Constraint::Percentage(40),
+            ]
+            .as_ref(),
+        )
         .split(area);
 
     draw_world_and_selection(f, selected_lf, tick_rate, world, chunks[0]);
-    draw_events(f, world, chunks[1]);
+    draw_chart(f, chart_series, world, chunks[1]);
+    draw_events(f, world, chunks[2]);
+}
+
+fn draw_chart<B>(f: &mut Frame<B>, chart_series: ChartSeries, world: &World, area: Rect)
+where
+    B: Backend,
+{
+    let history = &world.stats.history;
+    let recent = &history[history.len().saturating_sub(CHART_HISTORY_LENGTH)..];
+
+    let points = |value: &dyn Fn(&StatsRecord) -> f64| -> Vec<(f64, f64)> {
+        recent.iter().map(|r| (r.tic as f64, value(r))).collect()
+    };
+
+    // (name, color, points) for each line on the chart
+    let series = match chart_series {
+        ChartSeries::Population => vec![(
+            "lifeforms",
+            Color::Green,
+            points(&|r| r.population as f64),
+        )],
+        ChartSeries::AverageAge => vec![(
+            "average age",
+            Color::Cyan,
+            points(&|r| r.mean_lifespan as f64),
+        )],
+        ChartSeries::BirthsAndDeaths => vec![
+            ("births", Color::LightGreen, points(&|r| r.births as f64)),
+            ("deaths", Color::Blue, points(&|r| r.deaths as f64)),
+        ],
+        ChartSeries::Food => vec![("food", Color::Green, points(&|r| r.food as f64))],
+    };
+
+    let first_tic = recent.first().map_or(0.0, |r| r.tic as f64);
+    let last_tic = recent.last().map_or(0.0, |r| r.tic as f64);
+    let max_value = series
+        .iter()
+        .flat_map(|(_, _, points)| points.iter().map(|(_, y)| *y))
+        .fold(1.0, f64::max);
+
+    let datasets = series
+        .iter()
+        .map(|(name, color, points)| {
+            Dataset::default()
+                .name(*name)
+                .marker(symbols::Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(*color))
+                .data(points)
+        })
+        .collect();
+
+    let chart = Chart::new(datasets)
+        .block(
+            Block::default()
+                .title(format!("{} (c = next chart)", chart_series.title()))
+                .borders(Borders::ALL),
+        )
+        .x_axis(
+            Axis::default()
+                .bounds([first_tic, last_tic])
+                .labels(vec![
+                    Span::from(format!("{}", first_tic)),
+                    Span::from(format!("{}", last_tic)),
+                ]),
+        )
+        .y_axis(
+            Axis::default()
+                .bounds([0.0, max_value])
+                .labels(vec![Span::from("0"), Span::from(format!("{:.0}", max_value))]),
+        );
+
+    f.render_widget(chart, area);
 }
 
 fn draw_world_and_selection<B>(
@@ -443,8 +626,6 @@ where
     B: Backend,
 {
     // TODO
-    // * Dude the chart could be an amazing visualization for this, allowing us to see things
-    // like average age over time
     // * Get oldest LF
 
     let block = Block::default()
@@ -455,8 +636,10 @@ where
 
     items.push(
         ListItem::new(format!(
-            "Info: iteration: {} | tick rate: {}ms",
-            world.tics, tick_rate
+            "Info: iteration: {} | tick rate: {}ms | seed: {}",
+            world.tics,
+            tick_rate,
+            world.props().seed
         ))
         .style(Style::default().fg(Color::Cyan)),
     );
@@ -466,18 +649,51 @@ where
             .style(Style::default().fg(Color::Green)),
     );
 
-    let average_age: f32 = world
-        .lifeforms
-        .values()
-        .map(|lf| lf.lifespan as f32)
-        .sum::<f32>()
-        / world.lifeforms.len() as f32;
-
     items.push(
-        ListItem::new(format!("Average Age: {}", average_age))
+        ListItem::new(format!("Average Age: {}", world.average_age()))
             .style(Style::default().fg(Color::Green)),
     );
 
+    items.push(
+        ListItem::new(format!(
+            "Average Mutation Rate: {:.4}",
+            world.average_mutation_rate()
+        ))
+        .style(Style::default().fg(Color::Green)),
+    );
+
+    if let Some(largest) = world.speciation.largest() {
+        items.push(
+            ListItem::new(Span::styled(
+                format!(
+                    "Species: {} | largest: {} ({} members)",
+                    world.speciation.species.len(),
+                    largest.id,
+                    largest.members
+                ),
+                Style::default().fg(species_color(Some(largest.id))),
+            )),
+        );
+    }
+
+    let mut founders = BTreeMap::new();
+
+    for lf in world.lifeforms.values() {
+        *founders.entry(lf.founder_id).or_insert(0) += 1;
+    }
+
+    if let Some((founder, count)) = founders.iter().max_by_key(|(_, count)| **count) {
+        items.push(
+            ListItem::new(format!(
+                "Founding Lines: {} | largest: LF {} ({} alive)",
+                founders.len(),
+                founder,
+                count
+            ))
+            .style(Style::default().fg(Color::Green)),
+        );
+    }
+
     let list = List::new(items).block(block);
 
     f.render_widget(list, area);
@@ -496,13 +712,15 @@ where
             EventType::Mate => Color::Magenta,
             EventType::Attack => Color::Red,
             EventType::AsexuallyReproduce => Color::LightGreen,
+            EventType::NewSpecies => Color::Yellow,
+            EventType::Extinction => Color::DarkGray,
         };
 
         items.push(
-            ListItem::new(Span::from(Span::styled(
+            ListItem::new(Span::styled(
                 description,
                 Style::default().fg(color),
-            ))),
+            )),
         );
     }
 
@@ -516,11 +734,11 @@ fn generate_neuron_hashmap(
     neural_net: &NeuralNet,
     area: &Rect,
 ) -> HashMap<usize, (String, (f64, f64))> {
-    let max_names_per_line = neural_net.input_neurons.len() as u16 + 1;
+    let max_names_per_line = neural_net.input_neurons().count() as u16 + 1;
 
     let input_neuron_spacing = area.width as f64 / (max_names_per_line) as f64;
-    let inner_neuron_spacing = area.width as f64 / (neural_net.inner_neurons.len() + 1) as f64;
-    let output_neuron_spacing = area.width as f64 / (neural_net.output_neurons.len() + 1) as f64;
+    let inner_neuron_spacing = area.width as f64 / (neural_net.inner_neurons().count() + 1) as f64;
+    let output_neuron_spacing = area.width as f64 / (neural_net.output_neurons().count() + 1) as f64;
 
     let output_neuron_row = 1;
     let inner_neuron_row = (area.height / 2) - 2;
@@ -528,7 +746,7 @@ fn generate_neuron_hashmap(
 
     let mut neuron_location_map = HashMap::new();
 
-    for (idx, (_neuron_type, neuron)) in neural_net.input_neurons.values().enumerate() {
+    for (idx, (_neuron_type, neuron)) in neural_net.input_neurons().enumerate() {
         let row = input_neuron_row - ((idx as u16 / max_names_per_line) * 2);
 
         neuron_location_map.insert(
@@ -543,7 +761,7 @@ fn generate_neuron_hashmap(
         );
     }
 
-    for (idx, neuron) in neural_net.inner_neurons.values().enumerate() {
+    for (idx, neuron) in neural_net.inner_neurons().enumerate() {
         neuron_location_map.insert(
             neuron.id,
             (
@@ -556,7 +774,7 @@ fn generate_neuron_hashmap(
         );
     }
 
-    for (idx, (neuron_type, neuron)) in neural_net.output_neurons.values().enumerate() {
+    for (idx, (neuron_type, neuron)) in neural_net.output_neurons().enumerate() {
         neuron_location_map.insert(
             neuron.id,
             (
@@ -1,62 +1,10 @@
-use std::collections::HashMap;
+use std::collections::BTreeSet;
 
-use crate::*;
-use rand::{thread_rng, Rng};
-
-/// Generates a vec that has a very specific set of information relative to a lifeform, to be used
-/// later on with the close_lifeform_info_from_info_vec function
-pub fn generate_lifeform_info_vec(
-    lifeforms: &HashMap<usize, LifeForm>,
-) -> Vec<(usize, (usize, usize), f32)> {
-    lifeforms
-        .values()
-        .map(|lifeform| (lifeform.id, lifeform.location, lifeform.health))
-        .collect()
-}
-
-/// Takes id and location of the thing you're trying to find the closest other thing to, very
-/// specificly constructed vector
-/// Returns (
-///     num_in_vicinity, (number of lifeforms within the vicinity of the lifeform)
-///     health,  (of closest lf)
-///     loc, (of closest lf)
-///     distance (of closest lf)
-/// )
-pub fn close_lifeform_info_from_info_vec(
-    size: usize,
-    id: &usize,
-    location: &(usize, usize),
-    lfs_id_loc_health: &Vec<(usize, (usize, usize), f32)>,
-) -> (usize, f32, (usize, usize), f32) {
-    let mut number_in_vicinity: usize = 0;
-    let mut closest_lf_health: f32 = 0.0;
-    let mut closest_lf_location: (usize, usize) = (0, 0);
-    let mut closest_lf_distance = f32::INFINITY;
-    for (object_id, loc, health) in lfs_id_loc_health {
-        if object_id == id {
-            continue;
-        }
-
-        let dist = dist_rel(size, location, loc);
-
-        if dist < closest_lf_distance {
-            closest_lf_health = *health;
-            closest_lf_distance = dist;
-            closest_lf_location = *loc;
-        }
-
-        if dist < 2.0 {
-            number_in_vicinity += 1;
-        }
-    }
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
 
-    (
-        number_in_vicinity,
-        closest_lf_health,
-        closest_lf_location,
-        closest_lf_distance,
-    )
-}
+use crate::*;
+use rand::Rng;
 
 pub fn closest_to(subject: &(usize, usize), objects: &Vec<(usize, usize)>) -> (usize, usize) {
     let mut shortest_distance = f32::INFINITY;
@@ -95,11 +43,10 @@ pub fn dist_rel(world_size: usize, from: &(usize, usize), to: &(usize, usize)) -
 /// Returns one of the four cardinal directions, and returns north if it's the same point.
 /// And assumes that the start point is in the bottom left corner.
 pub fn direc(from: &(usize, usize), to: &(usize, usize)) -> DirectionName {
-    let x1 = from.0 as f32;
-    let y1 = from.1 as f32;
-    let x2 = to.0 as f32;
-    let y2 = to.1 as f32;
+    direc_between((from.0 as f32, from.1 as f32), (to.0 as f32, to.1 as f32))
+}
 
+fn direc_between((x1, y1): (f32, f32), (x2, y2): (f32, f32)) -> DirectionName {
     if y2 > y1 && y2 + y1 > x2 + x1 {
         return DirectionName::North;
     } else if x2 > x1 && x2 + x1 > y2 + y1 {
@@ -135,7 +82,7 @@ pub fn direc(from: &(usize, usize), to: &(usize, usize)) -> DirectionName {
 }
 
 /// Relocate one step randomly
-pub fn randomize(size: usize, mut loc: &mut (usize, usize)) {
+pub fn randomize(size: usize, loc: &mut (usize, usize), rng: &mut impl Rng) {
     if loc.0 == 0 {
         loc.0 = 1;
         return;
@@ -150,21 +97,17 @@ pub fn randomize(size: usize, mut loc: &mut (usize, usize)) {
         return;
     }
 
-    if thread_rng().gen_bool(0.5) {
-        if thread_rng().gen_bool(0.5) {
+    if rng.gen_bool(0.5) {
+        if rng.gen_bool(0.5) {
             loc.0 += 1;
-            return;
         } else {
             loc.0 -= 1;
-            return;
         }
     } else {
-        if thread_rng().gen_bool(0.5) {
+        if rng.gen_bool(0.5) {
             loc.1 += 1;
-            return;
         } else {
             loc.1 -= 1;
-            return;
         }
     }
 }
@@ -211,33 +154,30 @@ pub fn rel_dir(
     orientation: &(i8, i8),
     other_location: &(usize, usize),
 ) -> f32 {
+    let offset = (
+        other_location.0 as f32 - self_location.0 as f32,
+        other_location.1 as f32 - self_location.1 as f32,
+    );
+
+    rel_dir_of(offset, orientation)
+}
+
+/// rel_dir, given the steps it takes to get from self to the other location.
+fn rel_dir_of(offset: (f32, f32), orientation: &(i8, i8)) -> f32 {
     // Gotta handle when it's the same point
-    if self_location == other_location {
+    if offset == (0.0, 0.0) {
         return 0.0;
     }
 
-    // self
-    let xs = self_location.0 as f32;
-    let ys = self_location.1 as f32;
-
-    // self plus orientation
-    let xsor = xs + orientation.0 as f32;
-    let ysor = ys + orientation.1 as f32;
-
-    // other
-    let xo = other_location.0 as f32;
-    let yo = other_location.1 as f32;
-
     // Great formula from https://stackoverflow.com/a/31334882/2128027
 
     // result = atan2(P3.y - P1.y, P3.x - P1.x) -
     //          atan2(P2.y - P1.y, P2.x - P1.x);
 
-    // P1 is self, P2 is self plus or, P3 is other
-    // result = atan2(yo - ys, xo - xs) -
-    //          atan2(ysor - ys, xsor - xs);
-
-    let mut radians = (yo - ys).atan2(xo - xs) - (ysor - ys).atan2(xsor - xs);
+    // P1 is self, P2 is self plus orientation, P3 is other, so P3 - P1 is the offset and
+    // P2 - P1 is the orientation
+    let mut radians =
+        offset.1.atan2(offset.0) - (orientation.1 as f32).atan2(orientation.0 as f32);
 
     // We don't care about obtuse angles, this translates them to their acute brethren
     if radians > std::f32::consts::PI {
@@ -251,20 +191,151 @@ pub fn rel_dir(
 }
 
 /// Takes a mutable subject and moves it one step towards a given object, each being a location
+/// If a wall is in the way it tries stepping to either side of it instead.
 /// TODO Currently only does cardinal directions but could also be updated to get all 8.
-pub fn move_towards(size: usize, subject: &mut (usize, usize), object: &(usize, usize)) {
-    match direc(subject, object) {
-        DirectionName::North => update_location(size, subject, &(0, 1)),
-        DirectionName::East => update_location(size, subject, &(1, 0)),
-        DirectionName::South => update_location(size, subject, &(0, -1)),
-        DirectionName::West => update_location(size, subject, &(-1, 0)),
-        _ => ()
+pub fn move_towards(
+    topology: Topology,
+    size: usize,
+    subject: &mut (usize, usize),
+    object: &(usize, usize),
+    walls: &BTreeSet<(usize, usize)>,
+) {
+    let modifier: (i8, i8) = match topology.direc(size, subject, object) {
+        DirectionName::North => (0, 1),
+        DirectionName::East => (1, 0),
+        DirectionName::South => (0, -1),
+        DirectionName::West => (-1, 0),
+        _ => return,
+    };
+
+    let around = [
+        modifier,
+        (modifier.1, modifier.0),
+        (-modifier.1, -modifier.0),
+    ];
+
+    if let Some(to) = around
+        .iter()
+        .find_map(|modifier| step_to(topology, size, walls, subject, modifier))
+    {
+        *subject = to;
+    }
+}
+
+/// What happens at the edges of the world.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Topology {
+    /// The world stops at its edges, anything walking into one stays put
+    #[default]
+    Bounded,
+
+    /// Walking off one edge comes back in on the opposite one, on both axes, so there are no
+    /// edges or corners to hide in. Spaces 0 and size are next to each other. Distances and
+    /// directions go whichever way around is shortest.
+    Toroidal,
+}
+
+impl Topology {
+    /// The (x, y) steps along the shortest way from one location to another.
+    pub fn offset(self, size: usize, from: &(usize, usize), to: &(usize, usize)) -> (f32, f32) {
+        match self {
+            Topology::Bounded => (to.0 as f32 - from.0 as f32, to.1 as f32 - from.1 as f32),
+            Topology::Toroidal => (
+                wrapped_offset(size, from.0, to.0),
+                wrapped_offset(size, from.1, to.1),
+            ),
+        }
+    }
+
+    /// See dist_abs
+    pub fn dist_abs(self, size: usize, from: &(usize, usize), to: &(usize, usize)) -> f32 {
+        let (x, y) = self.offset(size, from, to);
+        (x.powi(2) + y.powi(2)).sqrt()
+    }
+
+    /// See dist_rel. On a torus the farthest apart two locations can be is half way around on
+    /// both axes, so that's what comes out as 1.0.
+    pub fn dist_rel(self, size: usize, from: &(usize, usize), to: &(usize, usize)) -> f32 {
+        match self {
+            Topology::Bounded => dist_rel(size, from, to),
+            Topology::Toroidal => {
+                let half_way = size.div_ceil(2) as f32;
+                self.dist_abs(size, from, to) / (2.0 * half_way.powi(2)).sqrt()
+            }
+        }
+    }
+
+    /// See rel_dir
+    pub fn rel_dir(
+        self,
+        size: usize,
+        self_location: &(usize, usize),
+        orientation: &(i8, i8),
+        other_location: &(usize, usize),
+    ) -> f32 {
+        rel_dir_of(self.offset(size, self_location, other_location), orientation)
+    }
+
+    /// See direc
+    pub fn direc(self, size: usize, from: &(usize, usize), to: &(usize, usize)) -> DirectionName {
+        let (x, y) = (from.0 as f32, from.1 as f32);
+        let (dx, dy) = self.offset(size, from, to);
+
+        direc_between((x, y), (x + dx, y + dy))
+    }
+
+    /// See update_location. On a torus, stepping off an edge wraps around to the other side.
+    pub fn update_location(self, size: usize, loc: &mut (usize, usize), modifier: &(i8, i8)) {
+        match self {
+            Topology::Bounded => update_location(size, loc, modifier),
+            Topology::Toroidal => {
+                let around = (size + 1) as isize;
+                loc.0 = (loc.0 as isize + modifier.0 as isize).rem_euclid(around) as usize;
+                loc.1 = (loc.1 as isize + modifier.1 as isize).rem_euclid(around) as usize;
+            }
+        }
+    }
+
+    /// Where loc ends up moved by offset, or None if that's off the edge of a bounded world.
+    pub fn translate(
+        self,
+        size: usize,
+        loc: &(usize, usize),
+        offset: (isize, isize),
+    ) -> Option<(usize, usize)> {
+        let x = loc.0 as isize + offset.0;
+        let y = loc.1 as isize + offset.1;
+
+        match self {
+            Topology::Bounded => {
+                let inside = |c: isize| (0..=size as isize).contains(&c);
+                (inside(x) && inside(y)).then_some((x as usize, y as usize))
+            }
+            Topology::Toroidal => {
+                let around = (size + 1) as isize;
+                Some((x.rem_euclid(around) as usize, y.rem_euclid(around) as usize))
+            }
+        }
+    }
+}
+
+/// The shortest way from one coordinate to another on an axis that wraps after size.
+fn wrapped_offset(size: usize, from: usize, to: usize) -> f32 {
+    let around = (size + 1) as isize;
+    let offset = (to as isize - from as isize).rem_euclid(around);
+
+    if offset > around / 2 {
+        (offset - around) as f32
+    } else {
+        offset as f32
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rand::thread_rng;
 
     #[test]
     fn test_relative_dir() {
@@ -440,11 +511,11 @@ mod tests {
 
         let l1 = (0, 1);
         let l2 = (1, 0);
-        assert_eq!(dist_abs(&l1, &l2), (2.0 as f32).sqrt());
+        assert_eq!(dist_abs(&l1, &l2), 2.0_f32.sqrt());
 
         let l1 = (1, 0);
         let l2 = (0, 1);
-        assert_eq!(dist_abs(&l1, &l2), (2.0 as f32).sqrt());
+        assert_eq!(dist_abs(&l1, &l2), 2.0_f32.sqrt());
     }
 
     #[test]
@@ -499,7 +570,7 @@ mod tests {
     fn test_randomize() {
         for _ in 0..100 {
             let mut loc = (5, 5);
-            randomize(10, &mut loc);
+            randomize(10, &mut loc, &mut thread_rng());
             assert_ne!(loc, (5, 5));
 
             // It has to move by only one though
@@ -507,4 +578,33 @@ mod tests {
             assert!([4,5,6].contains(&loc.1));
         }
     }
+
+    #[test]
+    fn wraps_around_a_torus() {
+        let torus = Topology::Toroidal;
+
+        // 0 and 20 are next to each other in a world of size 20
+        assert_eq!(torus.offset(20, &(0, 0), &(20, 20)), (-1.0, -1.0));
+        assert_eq!(torus.dist_abs(20, &(1, 5), &(19, 5)), 3.0);
+        assert_eq!(torus.dist_rel(20, &(0, 0), &(10, 10)), 1.0);
+
+        // Facing east, something just over the west edge is straight behind
+        assert_eq!(torus.rel_dir(20, &(0, 5), &(1, 0), &(20, 5)), 1.0);
+        assert_eq!(Topology::Bounded.rel_dir(20, &(0, 5), &(1, 0), &(20, 5)), 0.0);
+
+        let mut loc = (20, 0);
+        torus.update_location(20, &mut loc, &(1, -1));
+        assert_eq!(loc, (0, 20));
+        assert_eq!(torus.translate(20, &(19, 1), (3, -2)), Some((1, 20)));
+        assert_eq!(Topology::Bounded.translate(20, &(19, 1), (3, -2)), None);
+
+        // The danger hunts across the seam
+        let mut danger = (0, 10);
+        move_towards(torus, 20, &mut danger, &(18, 10), &BTreeSet::new());
+        assert_eq!(danger, (20, 10));
+
+        let mut danger = (0, 10);
+        move_towards(Topology::Bounded, 20, &mut danger, &(18, 10), &BTreeSet::new());
+        assert_eq!(danger, (1, 10));
+    }
 }
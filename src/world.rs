@@ -1,78 +1,409 @@
 use crate::*;
-use rand::{thread_rng, Rng};
+use rand::{seq::SliceRandom, thread_rng, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 use rayon::prelude::*;
-use std::collections::{HashMap, HashSet};
-
-const EVENTS_LENGTH: usize = 30;
-
-#[derive(Debug)]
-pub struct WorldProps<'a> {
+use serde::{Deserialize, Serialize};
+use std::collections::{btree_map::Entry, BTreeMap, BTreeSet};
+
+/// Everything that shapes how a world plays out. These can come from a config file (see
+/// WorldProps::load), from the command line, or both. Fields missing from a config file are
+/// filled in from the defaults.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorldProps {
     pub size: usize,
     pub num_initial_lifeforms: usize,
     pub genome_size: usize,
+
+    /// The mutation rate the first genomes start out with. Each genome carries its own from
+    /// there, see MutationProps::rate_sd.
     pub mutation_rate: f32,
+
+    /// How many inner neurons the initial genomes have. Mutation can change this from there.
     pub num_inner_neurons: usize,
     pub minimum_number_lifeforms: usize,
 
-    /// After how many tics does the danger randomly move a space
+    /// How far mutation is allowed to grow genomes
+    pub mutation: MutationProps,
+
+    /// How a child's genome is made out of its parents' when mating
+    pub crossover: CrossoverProps,
+
+    /// After how many tics does the danger randomly move a space, for dangers that don't say
     pub danger_delay: usize,
-    /// How much does it hurt. It's radioactive so this damage falls off with the square of its
-    /// distance.
+    /// How much does it hurt, for dangers that don't say. How the damage falls off with distance
+    /// depends on the danger, see Falloff.
     pub danger_damage: f32,
 
+    /// The dangers in the world and how each one behaves
+    pub hazards: Vec<HazardProps>,
+
     /// After how many frames does a new food appear
     pub food_density: usize,
 
-    pub neural_net_helper: &'a NeuralNetHelper,
+    /// How food grows and spreads once it's there
+    pub food: FoodProps,
+
+    /// What dead lifeforms leave behind
+    pub carrion: CarrionProps,
+
+    /// Seeds the world's random number generator. Two worlds with the same seed and the same
+    /// props will play out identically.
+    pub seed: u64,
+
+    /// Every how many tics a row of population statistics is recorded
+    pub stats_interval: usize,
+
+    /// How much hungrier every lifeform gets each tic
+    pub hunger_per_tic: f32,
+
+    /// How much health a lifeform gains from eating a food
+    pub food_health: f32,
+
+    /// How much hunger a lifeform loses from eating a food
+    pub food_hunger: f32,
+
+    /// What fraction of its health each side of an attack is left with
+    pub attack_health_factor: f32,
+
+    /// How much hungrier attacking makes the attacker
+    pub attack_hunger: f32,
+
+    /// How many clones of the most fit lifeform are made when the population is too low
+    pub num_clones: usize,
+
+    /// How many events are kept around for the UI
+    pub events_length: usize,
+
+    /// Whether lifeforms split when they eat enough food
+    pub asexual_reproduction: bool,
+
+    /// Whether two lifeforms on the same spot that both fire Mate have a child together
+    pub sexual_reproduction: bool,
+
+    /// How much health each parent puts into a child when mating. The child starts out with what
+    /// both parents put in, up to full health, and a lifeform can't mate unless it has more
+    /// health than this to give.
+    pub mate_cost: f32,
+
+    /// How the lifeforms are sorted into species
+    pub species: SpeciesProps,
+
+    /// Whether inner neurons carry their values over from one tic to the next, see
+    /// LifeForm::run_neural_net
+    pub recurrent: bool,
+
+    /// Where the walls go
+    pub walls: WallProps,
+
+    /// Whether the world stops at its edges or wraps around them
+    pub topology: Topology,
+}
+
+impl WorldProps {
+    /// What a brand new random genome looks like in this world.
+    pub fn genome_props<'a>(&self, neural_net_helper: &'a NeuralNetHelper) -> GenomeProps<'a> {
+        GenomeProps {
+            size: self.genome_size,
+            num_inner_neurons: self.num_inner_neurons,
+            mutation_rate: self.mutation_rate,
+            neural_net_helper,
+        }
+    }
+}
+
+impl Default for WorldProps {
+    fn default() -> Self {
+        Self {
+            size: 50,
+            num_initial_lifeforms: 20,
+            genome_size: 25,
+            mutation_rate: 0.1,
+            num_inner_neurons: 5,
+            minimum_number_lifeforms: 5,
+            mutation: MutationProps::default(),
+            crossover: CrossoverProps::default(),
+            danger_delay: 10,
+            danger_damage: 0.5,
+            // The one radioactive hunter the world has always had
+            hazards: vec![HazardProps {
+                start: Some((0, 0)),
+                ..HazardProps::default()
+            }],
+            food_density: 30,
+            food: FoodProps::default(),
+            carrion: CarrionProps::default(),
+            seed: thread_rng().gen(),
+            stats_interval: 100,
+            hunger_per_tic: 0.0001,
+            food_health: 0.3,
+            food_hunger: 0.3,
+            attack_health_factor: 0.5,
+            attack_hunger: 0.3,
+            num_clones: 3,
+            events_length: 30,
+            asexual_reproduction: true,
+            sexual_reproduction: true,
+            mate_cost: 0.5,
+            species: SpeciesProps::default(),
+            recurrent: false,
+            walls: WallProps::default(),
+            topology: Topology::Bounded,
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct World<'a> {
-    props: WorldProps<'a>,
-    pub lifeforms: HashMap<usize, LifeForm>,
-    pub food: HashSet<(usize, usize)>,
-    pub danger: (usize, usize),
+    props: WorldProps,
+    neural_net_helper: &'a NeuralNetHelper,
+    pub lifeforms: BTreeMap<usize, LifeForm>,
+    pub food: BTreeMap<(usize, usize), Food>,
+
+    /// Where dead lifeforms left carrion, and how much energy is left in each
+    pub carrion: BTreeMap<(usize, usize), f32>,
+    pub hazards: Vec<Hazard>,
+
+    /// Spaces nothing can move into, see WallProps
+    pub walls: BTreeSet<(usize, usize)>,
     oscillator: f32,
+
+    /// The single source of randomness for everything that happens in the world. Anything that
+    /// needs a random number borrows this, so that a seed fully determines a run. The parts of a
+    /// tic that run in parallel each take a seed from it and give every lifeform its own stream
+    /// from that seed, see lifeform_rng.
+    rng: ChaCha8Rng,
     pub tics: usize,
     pub events: Vec<(EventType, String)>,
+    pub stats: Stats,
+    pub lineage: Lineage,
+    pub speciation: Speciation,
+
+    /// The gene id of every connection a genome in this world has made
+    pub innovations: Innovations,
+
+    /// Where every lifeform is, by id, for the proximity queries
+    lifeform_index: SpatialIndex<usize>,
+
+    /// Where every food is
+    food_index: SpatialIndex<(usize, usize)>,
+
+    /// Where all the carrion is
+    carrion_index: SpatialIndex<(usize, usize)>,
+
+    /// Ids are handed out in order and never reused, so that the lineage stays unambiguous
+    next_lifeform_id: usize,
 }
 
-#[derive(Debug)]
+/// How close another lifeform has to be to count towards a lifeform's NeighborhoodDensity
+const VICINITY_RADIUS: f32 = 2.0;
+
+/// What a lifeform can sense about the things around it, see World::surroundings
+struct Surroundings {
+    closest_food: (usize, usize),
+    closest_carrion: Option<(usize, usize)>,
+    num_in_vicinity: usize,
+    closest_lf_health: f32,
+    closest_lf_loc: (usize, usize),
+    closest_lf_distance: f32,
+}
+
+/// What a lifeform decided to do this tic that involves others, see World::decide
+struct Decision {
+    lf_id: usize,
+
+    /// Where it was before it moved
+    from: (usize, usize),
+    attacks: Vec<usize>,
+    wants_to_mate: bool,
+}
+
+/// The random numbers one lifeform gets for one parallel part of a tic. Each lifeform gets its
+/// own stream, so what it draws doesn't depend on which thread it ran on or who ran before it.
+fn lifeform_rng(seed: u64, lf_id: usize) -> ChaCha8Rng {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    rng.set_stream(lf_id as u64);
+    rng
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum EventType {
     Death,
     Creation,
     Mate,
     Attack,
     AsexuallyReproduce,
+    NewSpecies,
+    Extinction,
 }
 
 impl<'a> World<'a> {
-    pub fn new(props: WorldProps<'a>) -> Self {
-        let neural_net_helper = &props.neural_net_helper;
-
-        // LifeForm generation
-        let mut lifeforms = HashMap::new();
+    pub fn new(props: WorldProps, neural_net_helper: &'a NeuralNetHelper) -> Self {
+        let mut rng = ChaCha8Rng::seed_from_u64(props.seed);
 
-        for lifeform_id in 0..props.num_initial_lifeforms {
-            lifeforms.insert(
-                lifeform_id,
-                LifeForm::new(lifeform_id, props.genome_size, &neural_net_helper),
-            );
-        }
+        let size = props.size;
+        let topology = props.topology;
+        let events = Vec::with_capacity(props.events_length * 3);
 
         // Food generation
-        let food = HashSet::new();
-        let danger = (0, 0);
+        let food = BTreeMap::new();
 
-        Self {
+        let walls = match &props.walls.map {
+            Some(path) => load_walls(path, size).expect("the map was checked by validate"),
+            None => generate_walls(props.topology, size, &props.walls, &mut rng),
+        };
+
+        let mut world = Self {
             props,
+            neural_net_helper,
             food,
-            danger,
-            lifeforms,
+            carrion: BTreeMap::new(),
+            hazards: vec![],
+            walls,
+            lifeforms: BTreeMap::new(),
             oscillator: 0.0,
+            rng,
             tics: 0,
-            events: Vec::with_capacity(EVENTS_LENGTH * 3),
+            events,
+            stats: Stats::new(),
+            lineage: Lineage::new(),
+            speciation: Speciation::new(),
+            innovations: Innovations::new(),
+            lifeform_index: SpatialIndex::new(size, topology),
+            food_index: SpatialIndex::new(size, topology),
+            carrion_index: SpatialIndex::new(size, topology),
+            next_lifeform_id: 0,
+        };
+
+        // LifeForm generation. The first lifeforms are founders, scattered around wherever the
+        // walls leave room.
+        for _ in 0..world.props.num_initial_lifeforms {
+            let id = world.next_lifeform_id();
+            let location = world.random_loc();
+            let genome_props = world.props.genome_props(neural_net_helper);
+            let lf = LifeForm::new(
+                id,
+                location,
+                genome_props,
+                &mut world.innovations,
+                &mut world.rng,
+            );
+
+            world.lineage.record_birth(&lf, 0);
+            world.lifeforms.insert(id, lf);
+        }
+
+        world.place_hazards();
+        world.reindex();
+        world.cluster_species();
+
+        world
+    }
+
+    /// Rebuild a world from a snapshot, picking up exactly where the saved world left off. The
+    /// neural net helper must have been built with the snapshot's num_inner_neurons.
+    pub fn from_snapshot(snapshot: WorldSnapshot, neural_net_helper: &'a NeuralNetHelper) -> Self {
+        let size = snapshot.props.size;
+        let topology = snapshot.props.topology;
+
+        let mut world = Self {
+            props: snapshot.props,
+            neural_net_helper,
+            lifeforms: snapshot.lifeforms,
+            food: snapshot.food.into_iter().collect(),
+            carrion: snapshot.carrion.into_iter().collect(),
+            hazards: snapshot.hazards,
+            walls: snapshot.walls,
+            oscillator: snapshot.oscillator,
+            rng: snapshot.rng,
+            tics: snapshot.tics,
+            events: snapshot.events,
+            stats: snapshot.stats,
+            lineage: snapshot.lineage,
+            speciation: snapshot.speciation,
+            innovations: snapshot.innovations,
+            lifeform_index: SpatialIndex::new(size, topology),
+            food_index: SpatialIndex::new(size, topology),
+            carrion_index: SpatialIndex::new(size, topology),
+            next_lifeform_id: snapshot.next_lifeform_id,
+        };
+
+        world.reindex();
+
+        world
+    }
+
+    /// Capture the full state of the world so it can be written to disk and resumed later.
+    pub fn snapshot(&self) -> WorldSnapshot {
+        WorldSnapshot {
+            version: SNAPSHOT_VERSION,
+            props: self.props.clone(),
+            lifeforms: self.lifeforms.clone(),
+            food: self.food.iter().map(|(loc, food)| (*loc, *food)).collect(),
+            carrion: self
+                .carrion
+                .iter()
+                .map(|(loc, energy)| (*loc, *energy))
+                .collect(),
+            hazards: self.hazards.clone(),
+            walls: self.walls.clone(),
+            oscillator: self.oscillator,
+            rng: self.rng.clone(),
+            tics: self.tics,
+            events: self.events.clone(),
+            stats: self.stats.clone(),
+            lineage: self.lineage.clone(),
+            speciation: self.speciation.clone(),
+            innovations: self.innovations.clone(),
+            next_lifeform_id: self.next_lifeform_id,
+        }
+    }
+
+    /// Rebuilds the spatial indexes from scratch. The world keeps them up to date as it steps,
+    /// so this is only needed after moving lifeforms or food around from outside the world.
+    pub fn reindex(&mut self) {
+        self.lifeform_index.clear();
+        self.food_index.clear();
+        self.carrion_index.clear();
+
+        for lf in self.lifeforms.values() {
+            self.lifeform_index.insert(lf.id, lf.location);
+        }
+
+        for loc in self.food.keys() {
+            self.food_index.insert(*loc, *loc);
+        }
+
+        for loc in self.carrion.keys() {
+            self.carrion_index.insert(*loc, *loc);
+        }
+    }
+
+    pub fn props(&self) -> &WorldProps {
+        &self.props
+    }
+
+    /// The mean lifespan of the living lifeforms, or 0 if there aren't any.
+    pub fn average_age(&self) -> f32 {
+        if self.lifeforms.is_empty() {
+            return 0.0;
+        }
+
+        self.lifeforms.values().map(|lf| lf.lifespan as f32).sum::<f32>()
+            / self.lifeforms.len() as f32
+    }
+
+    /// The mean of the mutation rates the living lifeforms' genomes carry.
+    pub fn average_mutation_rate(&self) -> f32 {
+        if self.lifeforms.is_empty() {
+            return 0.0;
         }
+
+        self.lifeforms
+            .values()
+            .map(|lf| lf.genome.mutation_rate)
+            .sum::<f32>()
+            / self.lifeforms.len() as f32
     }
 
     pub fn step(&mut self) {
@@ -80,119 +411,324 @@ impl<'a> World<'a> {
         self.oscillator = (self.tics as f32 / 10.0).sin();
 
         // Update resources
-        if self.tics % self.props.food_density == 0 {
+        grow_food(
+            &mut self.food,
+            &mut self.food_index,
+            &self.walls,
+            &self.props,
+            &mut self.rng,
+        );
+
+        if self.tics.is_multiple_of(self.props.food_density) {
             self.generate_food();
         }
 
-        self.update_inputs();
+        decay_carrion(
+            &mut self.carrion,
+            &mut self.carrion_index,
+            &self.props.carrion,
+        );
 
-        // To avoid interior mutability, this keeps track of which lifeforms
-        // are marked as deceased and will be removed after the mutable loop.
-        let mut has_died: Vec<usize> = vec![];
-        let mut has_split: Vec<((usize, usize), Genome)> = vec![];
-
-        // do effects of environment on lifeforms
-        for mut lf in self.lifeforms.values_mut() {
-            lf.hunger += 0.0001;
-            lf.lifespan += 1;
-
-            // If the lifeform is on a resource, remove it
-            if self.food.remove(&lf.location) {
-                lf.hunger -= 0.3;
-                lf.health += 0.3;
-                if lf.hunger < 0.0 {
-                    lf.hunger = 0.0;
-                    has_split.push((lf.location.clone(), lf.genome.clone()));
-                    self.events.push((
-                        EventType::AsexuallyReproduce,
-                        format!(
-                            "=> Lifeform {} has reproduced asexually by eating enough food!",
-                            lf.id
-                        ),
-                    ));
-                }
+        // Eating is the one effect of the environment lifeforms can compete over, so it's
+        // settled first, in id order, before everything else is done in parallel. Gives how
+        // much energy each one got out of what it ate, food and carrion together.
+        let mut has_eaten: BTreeMap<usize, f32> = BTreeMap::new();
+
+        for lf in self.lifeforms.values() {
+            if let Some(food) = self.food.remove(&lf.location) {
+                self.food_index.remove(lf.location, lf.location);
+                *has_eaten.entry(lf.id).or_default() += food.energy;
+            }
+
+            if let Some(energy) = self.carrion.remove(&lf.location) {
+                self.carrion_index.remove(lf.location, lf.location);
+                *has_eaten.entry(lf.id).or_default() += energy;
             }
+        }
+
+        // do effects of environment on lifeforms. Gives back whether each one split from eating
+        // enough and whether it died, in id order.
+        let props = &self.props;
+        let hazards = &self.hazards;
 
-            let dist_to_danger = dist_abs(&lf.location, &self.danger);
-            lf.health -= self.props.danger_damage / dist_to_danger.powi(2);
+        let outcomes: Vec<(usize, bool, bool)> = self
+            .lifeforms
+            .par_iter_mut()
+            .map(|(lf_id, lf)| {
+                let mut has_split = false;
+
+                lf.hunger += props.hunger_per_tic;
+                lf.lifespan += 1;
+
+                if let Some(energy) = has_eaten.get(lf_id) {
+                    lf.energy += energy;
+                    lf.hunger -= props.food_hunger * energy;
+                    lf.health += props.food_health * energy;
+                    if lf.hunger < 0.0 {
+                        lf.hunger = 0.0;
+                        has_split = props.asexual_reproduction;
+                    }
+                }
 
-            if lf.health <= 0.0 {
-                has_died.push(lf.id);
+                lf.health -= hazards
+                    .iter()
+                    .map(|hazard| hazard.damage_at(props.topology, props.size, &lf.location))
+                    .sum::<f32>();
+
+                (*lf_id, has_split, lf.health <= 0.0)
+            })
+            .collect();
+
+        let mut has_died: Vec<usize> = vec![];
+        // location, genome, parent id, parent generation, founder id
+        type Split = ((usize, usize), Genome, usize, usize, usize);
+        let mut has_split: Vec<Split> = vec![];
+
+        for (lf_id, split, died) in outcomes {
+            if split {
+                let lf = &self.lifeforms[&lf_id];
+                has_split.push((
+                    lf.location,
+                    lf.genome.clone(),
+                    lf.id,
+                    lf.generation,
+                    lf.founder_id,
+                ));
+                self.events.push((
+                    EventType::AsexuallyReproduce,
+                    format!(
+                        "=> Lifeform {} has reproduced asexually by eating enough food!",
+                        lf_id
+                    ),
+                ));
             }
 
-            // Don't need to show more than 30 events
-            if self.events.len() > EVENTS_LENGTH * 2 {
-                self.events.drain(..EVENTS_LENGTH);
+            if died {
+                has_died.push(lf_id);
             }
         }
 
-        // Let the danger hunt
-        if self.tics % self.props.danger_delay == 0 {
-            let closest_lf_loc = closest_to(
-                &self.danger,
-                &self.lifeforms.iter().map(|(_, lf)| lf.location).collect(),
+        // Don't need to show more than events_length events
+        if self.events.len() > self.props.events_length * 2 {
+            self.events.drain(..self.props.events_length);
+        }
+
+        // Let the dangers hunt, wander and patrol
+        let lifeform_index = &self.lifeform_index;
+
+        for hazard in &mut self.hazards {
+            hazard.step(
+                self.tics,
+                self.props.topology,
+                self.props.size,
+                &self.walls,
+                |loc| lifeform_index.nearest(*loc, |_| false).map(|(_, loc)| loc),
+                &mut self.rng,
             );
-            move_towards(self.props.size, &mut self.danger, &closest_lf_loc);
         }
 
         for lf_id in has_died {
             // TODO When a really healthy one dies, it'd be nice if it reproduced
-            self.lifeforms.remove(&lf_id);
+            let lf = self.lifeforms.remove(&lf_id).unwrap();
+            self.lifeform_index.remove(lf_id, lf.location);
+            self.stats.record_death();
+            self.lineage.record_death(lf_id, self.tics);
+
+            if self.props.carrion.enabled {
+                self.leave_carrion(lf.location, lf.energy * self.props.carrion.nutrition);
+            }
+
             self.events
                 .push((EventType::Death, format!("=> Lifeform {} has died!", lf_id)));
         }
 
-        for info in has_split {
-            let id = self.available_lifeform_id();
-            let mut genome = info.1;
+        for (location, mut genome, parent_id, parent_generation, founder_id) in has_split {
+            let id = self.next_lifeform_id();
 
-            if Evolver::should_mutate(self.props.mutation_rate) {
-                Evolver::mutate(&mut genome, self.props.neural_net_helper)
-            }
+            self.inherit(&mut genome);
 
-            self.lifeforms.insert(
+            let neural_net = self.neural_net_helper.spawn(&genome);
+
+            self.add_lifeform(LifeForm {
                 id,
-                LifeForm {
-                    id,
-                    genome,
-                    health: 1.0,
-                    hunger: 0.0,
-                    location: info.0,
-                    lifespan: 0,
-                    neural_net: self.props.neural_net_helper.spawn(),
-                    most_recent_output_neuron_values: None,
-                    orientation: Direction::new(),
-                },
-            );
+                parent_id: Some(parent_id),
+                other_parent_id: None,
+                generation: parent_generation + 1,
+                founder_id,
+                species_id: None,
+                genome,
+                health: 1.0,
+                hunger: 0.0,
+                location,
+                lifespan: 0,
+                energy: 0.0,
+                neural_net,
+                most_recent_output_neuron_values: None,
+                orientation: Direction::new(),
+            });
         }
 
-        // Run the neural net calculations. Uses rayon's par_iter() to parallelise the calculations
-        // across threads.
-        let all_output_neuron_values: Vec<(usize, Vec<(OutputNeuronType, f32)>)> = self
+        self.update_inputs();
+
+        // Every lifeform runs its neural net and decides what to do all at once, across threads,
+        // then what they decided is carried out one at a time in id order. Turning and moving
+        // only ever touch the lifeform doing them, so those are done straight away; attacking
+        // and mating involve others, so they wait.
+        let recurrent = self.props.recurrent;
+        let topology = self.props.topology;
+        let size = self.props.size;
+        let seed: u64 = self.rng.gen();
+        let lifeform_index = &self.lifeform_index;
+        let walls = &self.walls;
+
+        let decisions: Vec<Decision> = self
             .lifeforms
-            .par_iter()
-            .map(|(lf_id, lf)| (*lf_id, lf.run_neural_net(&self.props.neural_net_helper)))
+            .par_iter_mut()
+            .map(|(lf_id, lf)| {
+                let mut rng = lifeform_rng(seed, *lf_id);
+                let output_neuron_values = lf.run_neural_net(recurrent);
+
+                // Who was here before anyone moved
+                let mut others_here = lifeform_index.at(lf.location);
+                others_here.retain(|id| id != lf_id);
+
+                let decision = Self::decide(
+                    lf,
+                    &output_neuron_values,
+                    others_here,
+                    topology,
+                    size,
+                    walls,
+                    &mut rng,
+                );
+                lf.most_recent_output_neuron_values = Some(output_neuron_values);
+                decision
+            })
             .collect();
 
-        for (lf_id, output_neuron_values) in all_output_neuron_values {
-            self.process_output_neuron_values(&lf_id, &output_neuron_values);
-            self.lifeforms
-                .entry(lf_id)
-                .and_modify(|lf| lf.most_recent_output_neuron_values = Some(output_neuron_values));
+        let wants_to_mate = self.resolve(decisions);
+
+        if self.props.sexual_reproduction {
+            self.mate_lifeforms(&wants_to_mate);
         }
 
         self.ensure_lifeform_count();
+
+        if self.tics.is_multiple_of(self.props.species.interval) {
+            self.cluster_species();
+        }
+
+        if self.tics.is_multiple_of(self.props.stats_interval) {
+            // Its own randomness, so that watching a run doesn't change how it plays out
+            let mut stats_rng = ChaCha8Rng::seed_from_u64(self.props.seed ^ self.tics as u64);
+
+            self.stats
+                .record(self.tics, &self.lifeforms, self.food.len(), &mut stats_rng);
+        }
+    }
+
+    /// Sorts the lifeforms into species and makes events for the species that appeared or died
+    /// out.
+    fn cluster_species(&mut self) {
+        let changes = self
+            .speciation
+            .cluster(&mut self.lifeforms, &self.props.species, self.tics);
+
+        for change in changes {
+            match change {
+                SpeciesChange::Born(id) => self
+                    .events
+                    .push((EventType::NewSpecies, format!("Species {} appeared", id))),
+                SpeciesChange::Extinct(id, born) => self.events.push((
+                    EventType::Extinction,
+                    format!(
+                        "Species {} went extinct after {} tics",
+                        id,
+                        self.tics - born
+                    ),
+                )),
+            }
+        }
     }
 
     fn generate_food(&mut self) {
-        self.food.insert(self.random_loc());
+        let loc = self.random_loc();
+
+        if let Entry::Vacant(entry) = self.food.entry(loc) {
+            entry.insert(Food::grown());
+            self.food_index.insert(loc, loc);
+        }
+    }
+
+    /// Puts the dangers from the props in the world. The ones without a start, or whose start is
+    /// in a wall, go somewhere random, and patrollers without a path get one through a few
+    /// random spots.
+    fn place_hazards(&mut self) {
+        for props in self.props.hazards.clone() {
+            // Randomly scattered walls can't be checked for ahead of time like a map's can, so a
+            // start or waypoint that ended up in one is moved somewhere open
+            let location = match props.start {
+                Some(start) if !self.walls.contains(&start) => start,
+                _ => self.random_loc(),
+            };
+
+            let path = if props.behavior == HazardBehavior::Patroller && props.path.is_empty() {
+                (0..4).map(|_| self.random_loc()).collect()
+            } else {
+                props
+                    .path
+                    .iter()
+                    .map(|loc| {
+                        if self.walls.contains(loc) {
+                            self.random_loc()
+                        } else {
+                            *loc
+                        }
+                    })
+                    .collect()
+            };
+
+            let hazard = Hazard::new(&props, &self.props, location, path);
+            self.hazards.push(hazard);
+        }
     }
 
-    fn random_loc(&self) -> (usize, usize) {
-        (
-            thread_rng().gen_range(0..self.props.size),
-            thread_rng().gen_range(0..self.props.size),
-        )
+    /// Drops carrion where a lifeform died. Where there's carrion already the two pile up.
+    fn leave_carrion(&mut self, location: (usize, usize), energy: f32) {
+        if energy <= 0.0 {
+            return;
+        }
+
+        match self.carrion.entry(location) {
+            Entry::Occupied(mut entry) => *entry.get_mut() += energy,
+            Entry::Vacant(entry) => {
+                entry.insert(energy);
+                self.carrion_index.insert(location, location);
+            }
+        }
+    }
+
+    /// Somewhere on the board that isn't a wall. Picking spots at random usually finds one
+    /// quickly, but a world that's nearly all walls falls back to choosing from the open spaces.
+    fn random_loc(&mut self) -> (usize, usize) {
+        let size = self.props.size;
+
+        for _ in 0..100 {
+            let loc = (self.rng.gen_range(0..size), self.rng.gen_range(0..size));
+
+            if !self.walls.contains(&loc) {
+                return loc;
+            }
+        }
+
+        let open: Vec<(usize, usize)> = (0..size)
+            .flat_map(|x| (0..size).map(move |y| (x, y)))
+            .filter(|loc| !self.walls.contains(loc))
+            .collect();
+
+        *open
+            .choose(&mut self.rng)
+            .expect("the walls always leave somewhere open, see generate_walls and validate")
     }
 
     /// Keep a minimum number of lifeforms on the board. If there are none,
@@ -204,44 +740,43 @@ impl<'a> World<'a> {
         }
         // If there are none, we can't get some from the most fit, so we'll make
         // a whole batch of randoms.
-        if self.lifeforms.len() == 0 {
+        if self.lifeforms.is_empty() {
             for _ in 0..self.props.minimum_number_lifeforms {
-                let lf = LifeForm::new(
-                    self.available_lifeform_id(),
-                    self.props.genome_size,
-                    &self.props.neural_net_helper,
-                );
-                self.events.push((
-                    EventType::Creation,
-                    format!(
-                        "=> New lifeform {} has been created with a random genome due to insufficient population",
-                        &lf.id
-                    ),
-                ));
-                self.lifeforms.insert(lf.id, lf);
+                self.add_random_lifeform();
             }
 
             return;
         }
 
         // Make a few clones
-        for _ in 0..3 {
+        for _ in 0..self.props.num_clones {
             let most_fit_lf = self.most_fit_lifeform();
+            let most_fit_id = most_fit_lf.id;
+            let generation = most_fit_lf.generation + 1;
+            let founder_id = most_fit_lf.founder_id;
+            let location = most_fit_lf.location;
             let mut genome = most_fit_lf.genome.clone();
-            if genome.genes.len() == 0 {
+            if genome.genes.is_empty() {
                 panic!("genome: {:?}", genome);
             }
-            Evolver::mutate(&mut genome, &self.props.neural_net_helper);
-            let location = most_fit_lf.location.clone();
+            Evolver::mutate_rate(&mut genome, &self.props.mutation, &mut self.rng);
+            self.mutate(&mut genome);
+            let neural_net = self.neural_net_helper.spawn(&genome);
 
             let lf = LifeForm {
-                id: self.available_lifeform_id(),
+                id: self.next_lifeform_id(),
+                parent_id: Some(most_fit_id),
+                other_parent_id: None,
+                generation,
+                founder_id,
+                species_id: None,
                 health: 1.0,
                 location,
                 genome,
                 hunger: 0.0,
                 lifespan: 0,
-                neural_net: self.props.neural_net_helper.spawn(),
+                energy: 0.0,
+                neural_net,
                 most_recent_output_neuron_values: None,
                 orientation: Direction::new(),
             };
@@ -250,17 +785,26 @@ impl<'a> World<'a> {
                 EventType::Creation,
                 format!(
                     "=> New lifeform {} has been created based on lifeform {} due to insufficient population",
-                    &lf.id, most_fit_lf.id
+                    &lf.id, most_fit_id
                 ),
             ));
-            self.lifeforms.insert(lf.id, lf);
+            self.add_lifeform(lf);
         }
+    }
 
+    /// Makes a founder, a lifeform with a random genome and no parent, somewhere on the board.
+    fn add_random_lifeform(&mut self) {
+        let id = self.next_lifeform_id();
+        let location = self.random_loc();
+        let genome_props = self.props.genome_props(self.neural_net_helper);
         let lf = LifeForm::new(
-            self.available_lifeform_id(),
-            self.props.genome_size,
-            &self.props.neural_net_helper,
+            id,
+            location,
+            genome_props,
+            &mut self.innovations,
+            &mut self.rng,
         );
+
         self.events.push((
             EventType::Creation,
             format!(
@@ -268,6 +812,45 @@ impl<'a> World<'a> {
                 &lf.id
             ),
         ));
+        self.add_lifeform(lf);
+    }
+
+    /// Passing a genome on to a child: its mutation rate drifts, then that new rate decides
+    /// whether the genes get mutated too.
+    fn inherit(&mut self, genome: &mut Genome) {
+        Evolver::mutate_rate(genome, &self.props.mutation, &mut self.rng);
+
+        if Evolver::should_mutate(genome.mutation_rate, &mut self.rng) {
+            self.mutate(genome);
+        }
+    }
+
+    fn mutate(&mut self, genome: &mut Genome) {
+        let mutations = Evolver::mutate(
+            genome,
+            &self.props.mutation,
+            self.neural_net_helper,
+            &mut self.innovations,
+            &mut self.rng,
+        );
+
+        self.stats.record_mutations(mutations);
+    }
+
+    /// Puts a newly born lifeform on the board, and into the stats and the lineage.
+    fn add_lifeform(&mut self, mut lf: LifeForm) {
+        // Newborns belong to their parent's species until the next clustering says otherwise
+        if lf.species_id.is_none() {
+            lf.species_id = lf
+                .parent_id
+                .and_then(|id| self.lifeforms.get(&id))
+                .and_then(|parent| parent.species_id);
+        }
+
+        self.stats.record_birth();
+        self.lineage.record_birth(&lf, self.tics);
+        self.lifeform_index.insert(lf.id, lf.location);
+        self.lifeforms.insert(lf.id, lf);
     }
 
     fn most_fit_lifeform(&self) -> &LifeForm {
@@ -275,7 +858,7 @@ impl<'a> World<'a> {
 
         for lf in self.lifeforms.values() {
             if let Some(most_fit) = most_fit_lf {
-                if Evolver::fitness(lf) > Evolver::fitness(&most_fit) {
+                if Evolver::fitness(lf) > Evolver::fitness(most_fit) {
                     most_fit_lf = Some(lf);
                 }
             } else {
@@ -286,145 +869,318 @@ impl<'a> World<'a> {
         most_fit_lf.unwrap()
     }
 
-    fn process_output_neuron_values(
-        &mut self,
-        lf_id: &usize,
+    /// Works out which of the lifeform's output neurons fire, turning and moving it as they say.
+    /// What it wants to do to others is handed back for World::resolve.
+    fn decide(
+        lf: &mut LifeForm,
         values: &Vec<(OutputNeuronType, f32)>,
-    ) {
-        let other_lf_ids_at_loc =
-            self.other_lf_ids_at_location(*lf_id, &self.lifeforms[lf_id].location);
+        others_here: Vec<usize>,
+        topology: Topology,
+        size: usize,
+        walls: &BTreeSet<(usize, usize)>,
+        rng: &mut impl Rng,
+    ) -> Decision {
+        let mut decision = Decision {
+            lf_id: lf.id,
+            from: lf.location,
+            attacks: vec![],
+            wants_to_mate: false,
+        };
+
+        for (neuron_type, value) in values {
+            // This reads as continue on with the probability of value so long as value is above 0.
+            // Not every activation stays under 1, so anything past that always fires.
+            if value.is_nan() || *value <= 0.0 || !rng.gen_bool(value.min(1.0) as f64) {
+                continue;
+            }
 
-        // let mut lfs_to_mate_with: Vec<usize> = vec![];
-        let mut lfs_to_attack: Vec<usize> = vec![];
+            match neuron_type {
+                OutputNeuronType::TurnLeft => lf.orientation.turn_left(),
+                OutputNeuronType::TurnRight => lf.orientation.turn_right(),
+                OutputNeuronType::MoveForward => {
+                    let modifier = lf.orientation.get_forward_modifier();
 
-        {
-            let lf = self.lifeforms.get_mut(lf_id).unwrap();
-            let mut loc = &mut lf.location;
-            let size = self.props.size;
-
-            for (neuron_type, value) in values {
-                // This reads as continue on with the probability of value so long as value is above 0.
-                if *value <= 0.0 || !thread_rng().gen_bool(*value as f64) {
-                    return;
-                }
-
-                match neuron_type {
-                    OutputNeuronType::TurnLeft => lf.orientation.turn_left(),
-                    OutputNeuronType::TurnRight => lf.orientation.turn_right(),
-                    OutputNeuronType::MoveForward => {
-                        update_location(size, &mut loc, &lf.orientation.get_forward_modifier())
+                    if let Some(to) = step_to(topology, size, walls, &lf.location, &modifier) {
+                        lf.location = to;
                     }
-                    OutputNeuronType::Attack => other_lf_ids_at_loc
-                        .iter()
-                        .for_each(|id| lfs_to_attack.push(*id)),
                 }
+                OutputNeuronType::Attack => decision.attacks.extend(others_here.iter().copied()),
+                OutputNeuronType::Mate => decision.wants_to_mate = true,
             }
         }
 
-        // I'm just not totally sure if I want to completely remove mating. I might want to bring
-        // it back so I'm going to leave this here.
-        // for other_id in lfs_to_mate_with {
-        //     for _ in 0..2 {
-        //         let available_id = self.available_lifeform_id();
-        //         let location = self.lifeforms[lf_id].location;
-        //         let g1 = &self.lifeforms[lf_id].genome;
-        //         let g2 = &self.lifeforms[&other_id].genome;
-        //         let mut genome = Evolver::mate(&g1, &g2, &self.props.neural_net_helper);
-        //         if Evolver::should_mutate(self.props.mutation_rate) {
-        //             Evolver::mutate(&mut genome, &self.props.neural_net_helper);
-        //         }
-
-        //         self.lifeforms.entry(*lf_id).and_modify(|lf| {
-        //             lf.hunger += 0.5;
-        //             lf.health += 0.5;
-        //         });
-
-        //         self.lifeforms.entry(other_id).and_modify(|lf| {
-        //             lf.hunger += 0.5;
-        //             lf.health += 0.5;
-        //         });
-
-        //         let new_lf = LifeForm {
-        //             id: available_id,
-        //             genome,
-        //             health: 1.0,
-        //             hunger: 0.0,
-        //             lifespan: 0,
-        //             location,
-        //             neural_net: self.props.neural_net_helper.spawn(),
-        //         };
-
-        //         self.events.push((
-        //             EventType::Mate,
-        //             String::from(format!(
-        //                 "=> New lifeform {} was birthed from {lf_id} and {other_id}",
-        //                 &new_lf.id
-        //             )),
-        //         ));
-        //         self.lifeforms.insert(new_lf.id, new_lf);
-        //     }
-        // }
-
-        for other_id in lfs_to_attack {
-            self.lifeforms.entry(*lf_id).and_modify(|lf| {
-                lf.hunger += 0.3;
-                lf.health = lf.health / 2.0;
-            });
+        decision
+    }
 
-            self.lifeforms.entry(other_id).and_modify(|lf| {
-                lf.health = lf.health / 2.0;
-            });
+    /// Carries out what every lifeform decided this tic, in id order so it always comes out the
+    /// same. Gives back who wants to mate, since that's sorted out once everyone's in place.
+    fn resolve(&mut self, decisions: Vec<Decision>) -> BTreeSet<usize> {
+        let attack_health_factor = self.props.attack_health_factor;
+        let attack_hunger = self.props.attack_hunger;
+        let mut wants_to_mate: BTreeSet<usize> = BTreeSet::new();
+
+        for decision in &decisions {
+            let location = self.lifeforms[&decision.lf_id].location;
+            self.lifeform_index
+                .relocate(decision.lf_id, decision.from, location);
+        }
 
-            self.events.push((
-                EventType::Attack,
-                String::from(format!("=> {lf_id} just attacked {other_id}!!")),
-            ));
+        for Decision {
+            lf_id,
+            attacks,
+            wants_to_mate: mate,
+            ..
+        } in decisions
+        {
+            for other_id in attacks {
+                self.lifeforms.entry(lf_id).and_modify(|lf| {
+                    lf.hunger += attack_hunger;
+                    lf.health *= attack_health_factor;
+                });
+
+                self.lifeforms.entry(other_id).and_modify(|lf| {
+                    lf.health *= attack_health_factor;
+                });
+
+                self.stats.record_attack();
+
+                self.events.push((
+                    EventType::Attack,
+                    format!("=> {lf_id} just attacked {other_id}!!"),
+                ));
+            }
+
+            if mate {
+                wants_to_mate.insert(lf_id);
+            }
+        }
+
+        wants_to_mate
+    }
+
+    /// Pairs up lifeforms that share a spot and both want to mate. Nobody mates twice in a tic.
+    fn mate_lifeforms(&mut self, wants_to_mate: &BTreeSet<usize>) {
+        let mate_cost = self.props.mate_cost;
+
+        // Lifeforms that can't afford it aren't going anywhere
+        let wants_to_mate: BTreeSet<usize> = wants_to_mate
+            .iter()
+            .copied()
+            .filter(|id| self.lifeforms[id].health > mate_cost)
+            .collect();
+
+        let mut has_mated: BTreeSet<usize> = BTreeSet::new();
+
+        for lf_id in &wants_to_mate {
+            if has_mated.contains(lf_id) {
+                continue;
+            }
+
+            let location = self.lifeforms[lf_id].location;
+            let partner_id = self
+                .other_lf_ids_at_location(*lf_id, &location)
+                .into_iter()
+                .find(|id| wants_to_mate.contains(id) && !has_mated.contains(id));
+
+            if let Some(partner_id) = partner_id {
+                has_mated.insert(*lf_id);
+                has_mated.insert(partner_id);
+                self.mate(*lf_id, partner_id);
+            }
+        }
+    }
+
+    /// Both parents pay mate_cost in health and get one child together, its genome crossed over
+    /// from both of theirs.
+    fn mate(&mut self, lf_id: usize, partner_id: usize) {
+        let lf = &self.lifeforms[&lf_id];
+        let partner = &self.lifeforms[&partner_id];
+
+        let mut genome = Evolver::mate(
+            &lf.genome,
+            &partner.genome,
+            &self.props.crossover,
+            self.neural_net_helper,
+            &mut self.rng,
+        );
+        let generation = lf.generation.max(partner.generation) + 1;
+        let founder_id = lf.founder_id;
+        let location = lf.location;
+
+        self.inherit(&mut genome);
+
+        for id in [lf_id, partner_id] {
+            self.lifeforms.get_mut(&id).unwrap().health -= self.props.mate_cost;
         }
+
+        let id = self.next_lifeform_id();
+        let neural_net = self.neural_net_helper.spawn(&genome);
+
+        self.events.push((
+            EventType::Mate,
+            format!(
+                "=> New lifeform {} was birthed from {} and {}",
+                id, lf_id, partner_id
+            ),
+        ));
+        self.stats.record_mating();
+
+        self.add_lifeform(LifeForm {
+            id,
+            parent_id: Some(lf_id),
+            other_parent_id: Some(partner_id),
+            generation,
+            founder_id,
+            species_id: None,
+            genome,
+            health: (self.props.mate_cost * 2.0).min(1.0),
+            hunger: 0.0,
+            location,
+            lifespan: 0,
+            energy: 0.0,
+            neural_net,
+            most_recent_output_neuron_values: None,
+            orientation: Direction::new(),
+        });
     }
 
     /// Go through each lifeform and update the inputs for their neural_nets
-    fn update_inputs(&mut self) {
+    pub fn update_inputs(&mut self) {
         let (hlthst_lf_health, hlthst_lf_loc) = self.healthiest_lifeform_info();
-        let lfs_id_loc_health = generate_lifeform_info_vec(&self.lifeforms);
         let num_lifeforms = self.lifeforms.len();
         let size = self.props.size;
 
-        for (lifeform_id, lifeform) in self.lifeforms.iter_mut() {
-            let closest_food = &closest_to(
-                &lifeform.location,
-                &self.food.iter().map(|loc| *loc).collect(),
-            );
-            let loc = &lifeform.location;
-            let orm = &lifeform.orientation.get_forward_modifier();
+        let hazards = &self.hazards;
+        let oscillator = self.oscillator;
+        let walls = &self.walls;
+        let topology = self.props.topology;
+        let seed: u64 = self.rng.gen();
+
+        // Everything each lifeform can sense about what's around it, looked up before any of
+        // their inputs get written
+        let surroundings: Vec<Surroundings> = self
+            .lifeforms
+            .par_iter()
+            .map(|(_, lf)| self.surroundings(lf))
+            .collect();
+
+        let mut lifeforms: Vec<&mut LifeForm> = self.lifeforms.values_mut().collect();
+
+        lifeforms
+            .par_iter_mut()
+            .zip(surroundings)
+            .for_each(|(lifeform, surroundings)| {
+                let mut rng = lifeform_rng(seed, lifeform.id);
+                let Surroundings {
+                    closest_food,
+                    closest_carrion,
+                    num_in_vicinity,
+                    closest_lf_health,
+                    closest_lf_loc,
+                    closest_lf_distance,
+                } = surroundings;
+                let closest_food = &closest_food;
+                let loc = &lifeform.location;
+                let orm = &lifeform.orientation.get_forward_modifier();
+
+                // With no dangers around, it's as far away as can be
+                let closest_hazard = hazards.iter().min_by(|a, b| {
+                    let a = topology.dist_abs(size, loc, &a.location);
+                    let b = topology.dist_abs(size, loc, &b.location);
+                    a.total_cmp(&b)
+                });
+                let danger = closest_hazard.map(|hazard| hazard.location);
+
+                for (neuron_type, value) in lifeform.neural_net.input_values_mut() {
+                    *value = match neuron_type {
+                        InputNeuronType::Random => rng.gen_range(0.0..=1.0),
+                        InputNeuronType::Oscillator => oscillator,
+                        InputNeuronType::Health => lifeform.health,
+                        InputNeuronType::Hunger => lifeform.hunger,
+                        InputNeuronType::PopulationDensity => {
+                            num_lifeforms as f32 / size.pow(2) as f32
+                        }
+                        InputNeuronType::NeighborhoodDensity => {
+                            num_in_vicinity as f32 / num_lifeforms as f32
+                        }
+                        InputNeuronType::DirectionToFood => {
+                            topology.rel_dir(size, loc, orm, closest_food)
+                        }
+                        InputNeuronType::DistanceToFood => {
+                            topology.dist_rel(size, loc, closest_food)
+                        }
+                        InputNeuronType::DirectionToDanger => {
+                            danger.map_or(0.0, |d| topology.rel_dir(size, loc, orm, &d))
+                        }
+                        InputNeuronType::DistanceToDanger => {
+                            danger.map_or(1.0, |d| topology.dist_rel(size, loc, &d))
+                        }
+                        InputNeuronType::DangerKind => {
+                            closest_hazard.map_or(0.0, |hazard| hazard.menace())
+                        }
+                        InputNeuronType::DirectionToHealthiestLF => {
+                            topology.rel_dir(size, loc, orm, &hlthst_lf_loc)
+                        }
+                        InputNeuronType::DistanceToHealthiestLF => {
+                            topology.dist_rel(size, loc, &hlthst_lf_loc)
+                        }
+                        InputNeuronType::HealthiestLFHealth => hlthst_lf_health,
+                        InputNeuronType::DirectionToClosestLF => {
+                            topology.rel_dir(size, loc, orm, &closest_lf_loc)
+                        }
+                        InputNeuronType::DistanceToClosestLF => closest_lf_distance,
+                        InputNeuronType::ClosestLFHealth => closest_lf_health,
+                        InputNeuronType::WallAhead => {
+                            is_blocked(topology, size, walls, loc, orm) as u8 as f32
+                        }
+                        InputNeuronType::DirectionToCarrion => {
+                            closest_carrion.map_or(0.0, |c| topology.rel_dir(size, loc, orm, &c))
+                        }
+                        InputNeuronType::DistanceToCarrion => {
+                            closest_carrion.map_or(1.0, |c| topology.dist_rel(size, loc, &c))
+                        }
+                    };
+                }
+            });
+    }
 
-            let (num_in_vicinity, closest_lf_health, closest_lf_loc, closest_lf_distance) =
-                close_lifeform_info_from_info_vec(
-                    self.props.size,
-                    lifeform_id,
+    /// Looks up the closest food, carrion and lifeform to this lifeform, and how many others are
+    /// right around it.
+    fn surroundings(&self, lf: &LifeForm) -> Surroundings {
+        let closest_food = self
+            .food_index
+            .nearest(lf.location, |_| false)
+            .map(|(food, _)| food)
+            .unwrap_or((0, 0));
+
+        let closest_carrion = self
+            .carrion_index
+            .nearest(lf.location, |_| false)
+            .map(|(carrion, _)| carrion);
+
+        let num_in_vicinity = self
+            .lifeform_index
+            .within(lf.location, VICINITY_RADIUS)
+            .filter(|(id, _)| *id != lf.id)
+            .count();
+
+        let (closest_lf_health, closest_lf_loc, closest_lf_distance) =
+            match self.lifeform_index.nearest(lf.location, |id| *id == lf.id) {
+                Some((id, loc)) => (
+                    self.lifeforms[&id].health,
                     loc,
-                    &lfs_id_loc_health,
-                );
+                    self.props.topology.dist_rel(self.props.size, &lf.location, &loc),
+                ),
+                None => (0.0, (0, 0), f32::INFINITY),
+            };
 
-            for (_nid, (neuron_type, neuron)) in lifeform.neural_net.input_neurons.iter_mut() {
-                neuron.value = match neuron_type {
-                    InputNeuronType::Random => thread_rng().gen_range(0.0..=1.0),
-                    InputNeuronType::Oscillator => self.oscillator,
-                    InputNeuronType::Health => lifeform.health,
-                    InputNeuronType::Hunger => lifeform.hunger,
-                    InputNeuronType::PopulationDensity => num_lifeforms as f32 / size.pow(2) as f32,
-                    InputNeuronType::NeighborhoodDensity => num_in_vicinity as f32 / num_lifeforms as f32,
-                    InputNeuronType::DirectionToFood => rel_dir(loc, orm, closest_food),
-                    InputNeuronType::DistanceToFood => dist_rel(size, loc, closest_food),
-                    InputNeuronType::DirectionToDanger => rel_dir(loc, orm, &self.danger),
-                    InputNeuronType::DistanceToDanger => dist_rel(size, loc, &self.danger),
-                    InputNeuronType::DirectionToHealthiestLF => rel_dir(loc, orm, &hlthst_lf_loc),
-                    InputNeuronType::DistanceToHealthiestLF => dist_rel(size, loc, &hlthst_lf_loc),
-                    InputNeuronType::HealthiestLFHealth => hlthst_lf_health,
-                    InputNeuronType::DirectionToClosestLF => rel_dir(loc, orm, &closest_lf_loc),
-                    InputNeuronType::DistanceToClosestLF => closest_lf_distance,
-                    InputNeuronType::ClosestLFHealth => closest_lf_health,
-                };
-            }
+        Surroundings {
+            closest_food,
+            closest_carrion,
+            num_in_vicinity,
+            closest_lf_health,
+            closest_lf_loc,
+            closest_lf_distance,
         }
     }
 
@@ -442,35 +1198,380 @@ impl<'a> World<'a> {
         (healthiest_lifeform_health, healthiest_lifeform_location)
     }
 
-    fn available_lifeform_id(&self) -> usize {
-        let mut extent_ids: HashSet<usize> = HashSet::new();
-        for lf in self.lifeforms.values() {
-            extent_ids.insert(lf.id);
+    fn next_lifeform_id(&mut self) -> usize {
+        let id = self.next_lifeform_id;
+        self.next_lifeform_id += 1;
+        id
+    }
+
+    pub fn other_lf_ids_at_location(&self, id: usize, location: &(usize, usize)) -> Vec<usize> {
+        let mut lf_ids = self.lifeform_index.at(*location);
+        lf_ids.retain(|lf_id| *lf_id != id);
+        lf_ids
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn world_props(seed: u64) -> WorldProps {
+        WorldProps {
+            size: 30,
+            num_initial_lifeforms: 10,
+            genome_size: 15,
+            mutation_rate: 0.5,
+            food_density: 3,
+            num_inner_neurons: 3,
+            minimum_number_lifeforms: 5,
+            mutation: MutationProps {
+                max_genome_size: 30,
+                max_inner_neurons: 6,
+                ..MutationProps::default()
+            },
+            danger_delay: 5,
+            danger_damage: 0.5,
+            seed,
+            stats_interval: 10,
+            ..WorldProps::default()
         }
+    }
+
+    fn history(seed: u64, nnh: &NeuralNetHelper) -> String {
+        let mut world = World::new(world_props(seed), nnh);
+
+        for _ in 0..300 {
+            world.step();
+        }
+
+        serde_json::to_string(&world.snapshot()).unwrap()
+    }
+
+    #[test]
+    fn same_seed_same_history() {
+        let nnh = NeuralNetHelper::new();
+        assert_eq!(history(42, &nnh), history(42, &nnh));
+    }
+
+    #[test]
+    fn recording_stats_doesnt_change_the_history() {
+        let nnh = NeuralNetHelper::new();
+
+        // Enough lifeforms that genome_diversity samples pairs rather than comparing them all
+        let run = |stats_interval| {
+            let props = WorldProps {
+                num_initial_lifeforms: 60,
+                minimum_number_lifeforms: 50,
+                stats_interval,
+                ..world_props(42)
+            };
+            let mut world = World::new(props, &nnh);
 
-        let mut id: usize = 0;
+            for _ in 0..100 {
+                world.step();
+            }
+
+            let mut snapshot = world.snapshot();
+            snapshot.props.stats_interval = 0;
+            snapshot.stats = Stats::new();
+            serde_json::to_string(&snapshot).unwrap()
+        };
+
+        assert_eq!(run(1), run(1000));
+    }
+
+    #[test]
+    fn different_seed_different_history() {
+        let nnh = NeuralNetHelper::new();
+        assert_ne!(history(1, &nnh), history(2, &nnh));
+    }
+
+    #[test]
+    fn same_history_on_any_number_of_threads() {
+        let nnh = NeuralNetHelper::new();
+
+        let on_threads = |num_threads: usize| {
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(num_threads)
+                .build()
+                .unwrap()
+                .install(|| history(42, &nnh))
+        };
+
+        assert_eq!(on_threads(1), on_threads(4));
+    }
 
-        for potential_id in 0..=self.lifeforms.len() {
-            if !extent_ids.contains(&potential_id) {
-                id = potential_id;
-                break;
+    #[test]
+    fn nothing_gets_into_the_walls() {
+        let nnh = NeuralNetHelper::new();
+        let props = WorldProps {
+            walls: WallProps {
+                density: 0.3,
+                ..WallProps::default()
+            },
+            ..world_props(7)
+        };
+        let mut world = World::new(props, &nnh);
+
+        assert!(!world.walls.is_empty());
+
+        for _ in 0..300 {
+            world.step();
+
+            assert!(world.food.keys().all(|loc| !world.walls.contains(loc)));
+            for lf in world.lifeforms.values() {
+                assert!(!world.walls.contains(&lf.location));
             }
         }
+    }
 
-        id
+    #[test]
+    fn founders_start_out_all_over_the_board() {
+        let nnh = NeuralNetHelper::new();
+        let props = WorldProps {
+            num_initial_lifeforms: 100,
+            ..world_props(9)
+        };
+        let world = World::new(props, &nnh);
+
+        assert_eq!(world.lifeforms.len(), 100);
+
+        for lf in world.lifeforms.values() {
+            assert!(lf.location.0 < world.props.size && lf.location.1 < world.props.size);
+            assert!(!world.walls.contains(&lf.location));
+        }
     }
 
-    pub fn other_lf_ids_at_location(&self, id: usize, location: &(usize, usize)) -> Vec<usize> {
-        let mut lf_ids = vec![];
+    #[test]
+    fn senses_a_wall_ahead() {
+        let nnh = NeuralNetHelper::new();
+        let mut world = World::new(world_props(8), &nnh);
+
+        let wall_ahead = |world: &World| {
+            world.lifeforms[&0]
+                .neural_net
+                .input_neurons()
+                .find(|(neuron_type, _)| matches!(neuron_type, InputNeuronType::WallAhead))
+                .unwrap()
+                .1
+                .value
+        };
+
+        // Walled in on every side, so whichever way it's facing
+        world.lifeforms.get_mut(&0).unwrap().location = (10, 10);
+        world.walls = [
+            (9, 9),
+            (9, 10),
+            (9, 11),
+            (10, 9),
+            (10, 11),
+            (11, 9),
+            (11, 10),
+            (11, 11),
+        ]
+        .into();
+        world.update_inputs();
+        assert_eq!(wall_ahead(&world), 1.0);
+
+        world.walls.clear();
+        world.update_inputs();
+        assert_eq!(wall_ahead(&world), 0.0);
+    }
 
-        // TODO This could be sped up by keeping a hashmap of refs to the lifeforms keyed on their
-        // locations
-        for lf in self.lifeforms.values() {
-            if &lf.location == location && lf.id != id {
-                lf_ids.push(lf.id);
+    #[test]
+    fn indexes_keep_up_with_the_world() {
+        let nnh = NeuralNetHelper::new();
+
+        for topology in [Topology::Bounded, Topology::Toroidal] {
+            let props = WorldProps {
+                topology,
+                ..world_props(5)
+            };
+            let mut world = World::new(props, &nnh);
+
+            for _ in 0..300 {
+                world.step();
+
+                assert_eq!(world.lifeform_index.len(), world.lifeforms.len());
+                assert_eq!(world.food_index.len(), world.food.len());
+                assert_eq!(world.carrion_index.len(), world.carrion.len());
+            }
+
+            for lf in world.lifeforms.values() {
+                let expected: Vec<usize> = world
+                    .lifeforms
+                    .values()
+                    .filter(|other| other.id != lf.id && other.location == lf.location)
+                    .map(|other| other.id)
+                    .collect();
+
+                assert!(lf.location.0 <= world.props.size && lf.location.1 <= world.props.size);
+                assert_eq!(world.other_lf_ids_at_location(lf.id, &lf.location), expected);
+            }
+
+            for loc in world.food.keys() {
+                assert_eq!(world.food_index.at(*loc), vec![*loc]);
             }
         }
+    }
 
-        lf_ids
+    #[test]
+    fn the_dead_leave_carrion_to_be_eaten() {
+        let nnh = NeuralNetHelper::new();
+        let props = WorldProps {
+            carrion: CarrionProps {
+                enabled: true,
+                ..CarrionProps::default()
+            },
+            ..world_props(4)
+        };
+        let mut world = World::new(props, &nnh);
+
+        {
+            let lf = world.lifeforms.get_mut(&0).unwrap();
+            lf.location = (20, 20);
+            lf.energy = 2.0;
+            lf.health = -1.0;
+        }
+        world.reindex();
+        world.step();
+
+        assert!(!world.lifeforms.contains_key(&0));
+        assert_eq!(world.carrion[&(20, 20)], 1.0);
+
+        let energy_before = world.lifeforms[&1].energy;
+        world.lifeforms.get_mut(&1).unwrap().location = (20, 20);
+        world.reindex();
+        world.step();
+
+        assert!(world.carrion.is_empty());
+        assert!(world.lifeforms[&1].energy >= energy_before + 0.99);
+    }
+
+    #[test]
+    fn every_danger_goes_its_own_way() {
+        let nnh = NeuralNetHelper::new();
+        let props = WorldProps {
+            hazards: vec![
+                HazardProps {
+                    behavior: HazardBehavior::Stationary,
+                    start: Some((3, 4)),
+                    ..HazardProps::default()
+                },
+                HazardProps {
+                    behavior: HazardBehavior::Patroller,
+                    ..HazardProps::default()
+                },
+                HazardProps {
+                    behavior: HazardBehavior::Wanderer,
+                    delay: Some(1),
+                    ..HazardProps::default()
+                },
+            ],
+            ..world_props(6)
+        };
+        let mut world = World::new(props, &nnh);
+
+        assert_eq!(world.hazards.len(), 3);
+        assert_eq!(world.hazards[1].path.len(), 4);
+        assert_eq!(world.hazards[1].delay, 5);
+        let wanderer_start = world.hazards[2].location;
+        let mut wandered = false;
+
+        for _ in 0..100 {
+            world.step();
+            wandered |= world.hazards[2].location != wanderer_start;
+        }
+
+        assert_eq!(world.hazards[0].location, (3, 4));
+        assert!(wandered);
+    }
+
+    #[test]
+    fn dangers_stay_out_of_the_walls() {
+        let nnh = NeuralNetHelper::new();
+
+        for seed in 0..10 {
+            let props = WorldProps {
+                walls: WallProps {
+                    density: 0.6,
+                    ..WallProps::default()
+                },
+                hazards: vec![HazardProps {
+                    behavior: HazardBehavior::Patroller,
+                    start: Some((5, 5)),
+                    path: (0..10).map(|i| (i, i)).collect(),
+                    ..HazardProps::default()
+                }],
+                ..world_props(seed)
+            };
+            let world = World::new(props, &nnh);
+            let hazard = &world.hazards[0];
+
+            assert!(!world.walls.contains(&hazard.location));
+            assert_eq!(hazard.path.len(), 10);
+            assert!(hazard.path.iter().all(|loc| !world.walls.contains(loc)));
+        }
+    }
+
+    #[test]
+    fn never_reuses_ids_and_tracks_parents() {
+        let nnh = NeuralNetHelper::new();
+        let mut world = World::new(world_props(3), &nnh);
+
+        for _ in 0..300 {
+            world.step();
+        }
+
+        // Every lifeform that ever lived got its own record
+        assert_eq!(world.lineage.records.len(), world.next_lifeform_id);
+
+        for lf in world.lifeforms.values() {
+            let record = &world.lineage.records[&lf.id];
+            assert_eq!(record.parent_id, lf.parent_id);
+            assert_eq!(record.died, None);
+            // Children of mating are a generation past their older parent, so following only
+            // the first parent can come up short
+            assert!(world.lineage.ancestors(lf.id).len() <= lf.generation);
+            assert_eq!(world.lineage.founder(lf.id), lf.founder_id);
+            assert_eq!(world.lineage.records[&lf.founder_id].parent_id, None);
+        }
+    }
+
+    #[test]
+    fn mating_takes_two_willing_lifeforms() {
+        let nnh = NeuralNetHelper::new();
+        let mut world = World::new(
+            WorldProps {
+                num_initial_lifeforms: 3,
+                ..world_props(9)
+            },
+            &nnh,
+        );
+
+        for lf in world.lifeforms.values_mut() {
+            lf.location = (5, 5);
+        }
+        world.reindex();
+
+        // Only one of them wants to, so nothing happens
+        world.mate_lifeforms(&BTreeSet::from([1]));
+        assert_eq!(world.lifeforms.len(), 3);
+
+        world.mate_lifeforms(&BTreeSet::from([0, 2]));
+        assert_eq!(world.lifeforms.len(), 4);
+
+        let child = &world.lifeforms[&3];
+        assert_eq!(child.parent_id, Some(0));
+        assert_eq!(child.other_parent_id, Some(2));
+        assert_eq!(child.generation, 1);
+        assert_eq!(child.location, (5, 5));
+        assert_eq!(child.health, 1.0);
+        assert_eq!(world.lifeforms[&0].health, 0.5);
+        assert_eq!(world.lifeforms[&1].health, 1.0);
+
+        // Now they can't afford it
+        world.mate_lifeforms(&BTreeSet::from([0, 2]));
+        assert_eq!(world.lifeforms.len(), 4);
     }
 }